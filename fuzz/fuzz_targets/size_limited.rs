@@ -0,0 +1,37 @@
+//! Chunks the data with `max_size()`, and optionally `min_size()`, using both
+//! `slices()` and `stream()`.
+
+#![no_main]
+
+use cdchunking::{Chunker, ChunkerImpl, MinSizeLimited};
+use cdchunking_fuzz::{check_chunks, make_chunker, slices, split, stream, PARAMS};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let (params, data) = match split(data, PARAMS + 4) {
+        Some(s) => s,
+        None => return,
+    };
+    let (params, options) = params.split_at(PARAMS);
+    let max_size = u16::from_le_bytes([options[0], options[1]]) as usize % 4096 + 1;
+    let min_size = options[2] as usize;
+    let (sizes, data) = match split(data, options[3] as usize % 16) {
+        Some(s) => s,
+        None => return,
+    };
+
+    let new_chunker = || {
+        let chunker = make_chunker(params);
+        let chunker: Box<dyn ChunkerImpl> = if min_size > 0 {
+            Box::new(MinSizeLimited::new(chunker, min_size))
+        } else {
+            chunker
+        };
+        Chunker::new(chunker).max_size(max_size)
+    };
+    let expected = slices(new_chunker(), data);
+    check_chunks(data, &expected, Some(max_size));
+    let chunks = stream(new_chunker(), data, sizes, 4096);
+    check_chunks(data, &chunks, Some(max_size));
+    assert_eq!(chunks, expected);
+});
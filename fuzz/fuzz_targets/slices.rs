@@ -0,0 +1,16 @@
+//! Chunks the data with `slices()`.
+
+#![no_main]
+
+use cdchunking::Chunker;
+use cdchunking_fuzz::{check_chunks, make_chunker, slices, split, PARAMS};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let (params, data) = match split(data, PARAMS) {
+        Some(s) => s,
+        None => return,
+    };
+    let chunks = slices(Chunker::new(make_chunker(params)), data);
+    check_chunks(data, &chunks, None);
+});
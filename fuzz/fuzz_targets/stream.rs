@@ -0,0 +1,26 @@
+//! Chunks the data with `stream()`, over a reader returning parts of
+//! arbitrary sizes, and compares with `slices()`.
+
+#![no_main]
+
+use cdchunking::Chunker;
+use cdchunking_fuzz::{check_chunks, make_chunker, slices, split, stream, PARAMS};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let (params, data) = match split(data, PARAMS + 2) {
+        Some(s) => s,
+        None => return,
+    };
+    let (params, options) = params.split_at(PARAMS);
+    // Buffer capacity, and the read sizes
+    let capacity = options[0] as usize + 1;
+    let (sizes, data) = match split(data, options[1] as usize % 16) {
+        Some(s) => s,
+        None => return,
+    };
+
+    let chunks = stream(Chunker::new(make_chunker(params)), data, sizes, capacity);
+    check_chunks(data, &chunks, None);
+    assert_eq!(chunks, slices(Chunker::new(make_chunker(params)), data));
+});
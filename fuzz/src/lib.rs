@@ -0,0 +1,196 @@
+//! Helpers shared by the fuzz targets.
+//!
+//! The first `PARAMS` bytes of the fuzzer input select a chunker and its
+//! parameters, the targets then take what else they need, and the rest is the
+//! data to chunk.
+
+use std::cmp::min;
+use std::io::{self, Read};
+
+use cdchunking::{
+    AEChunker, AdlerChunker, BFBCChunker, BuzhashChunker, CasyncChunker, ChunkInput, Chunker,
+    ChunkerImpl, FastCDC, FastCDC2020, FixedSizeChunker, GearChunker, MIIChunker,
+    MaybeOptimizedRAMChunker, NormalizedChunkingGearChunker, PCIChunker, Polynomial, RAMChunker,
+    RabinChunker, RabinHash, ResticChunker, TTTDChunker, ZPAQCompatible, ZPAQ,
+};
+
+/// Number of bytes used by `make_chunker()`.
+pub const PARAMS: usize = 8;
+
+/// Splits `n` bytes off the start of the input, if there are enough.
+pub fn split(data: &[u8], n: usize) -> Option<(&[u8], &[u8])> {
+    if data.len() < n {
+        None
+    } else {
+        Some(data.split_at(n))
+    }
+}
+
+/// A mask with the given number of most-significant bits set.
+fn mask(bits: u8) -> u32 {
+    (!0u32).checked_shl(32 - bits as u32).unwrap_or(0)
+}
+
+/// Creates a chunker from the parameters, small enough to find boundaries.
+pub fn make_chunker(p: &[u8]) -> Box<dyn ChunkerImpl> {
+    let u16 = |i: usize| u16::from_le_bytes([p[i], p[i + 1]]) as usize;
+    match p[0] % 19 {
+        0 => Box::new(FixedSizeChunker::new(p[1] as usize + 1)),
+        1 => Box::new(GearChunker::new(mask(p[1] % 12 + 1))),
+        2 => Box::new(NormalizedChunkingGearChunker::new(
+            mask(p[1] % 12 + 2),
+            mask(p[1] % 12),
+            u16(2),
+        )),
+        3 | 4 => {
+            let bits = p[1] % 8 + 3;
+            let avg = 1 << bits;
+            let min_size = 1 + p[2] as usize % avg;
+            let max_size = avg + p[3] as usize * 4;
+            let level = (p[4] % 4).min(bits - 1) as u32;
+            if p[0] % 19 == 3 {
+                Box::new(FastCDC::with_level(min_size, avg, max_size, level))
+            } else {
+                Box::new(FastCDC2020::with_level(min_size, avg, max_size, level))
+            }
+        }
+        5 => Box::new(ZPAQ::new(p[1] as usize % 14 + 1)),
+        6 => Box::new(ZPAQCompatible::new(p[1] as u32 % 3)),
+        7 => Box::new(AEChunker::new(p[1] as usize + 1)),
+        8 => Box::new(RAMChunker::new(p[1] as usize + 1)),
+        9 => Box::new(MaybeOptimizedRAMChunker::new(p[1] as usize + 1)),
+        10 => Box::new(MIIChunker::new(p[1] as usize % 8 + 1)),
+        11 => Box::new(PCIChunker::<3>::new(p[1] as u32 % 25)),
+        12 => {
+            let pairs = vec![(p[1], p[2]), (p[3], p[4]), (p[5], p[6])];
+            Box::new(BFBCChunker::new(pairs, p[7] as usize + 2))
+        }
+        13 => {
+            let mask = (1 << (p[3] % 12)) - 1;
+            Box::new(RabinChunker::new(
+                Polynomial::generate(p[1] as u64),
+                p[2] as usize % 64 + 1,
+                mask,
+                p[4] as u64 & mask,
+            ))
+        }
+        14 => {
+            let min_size = 64 + p[2] as usize * 4;
+            Box::new(ResticChunker::with_boundaries(
+                Polynomial::generate(p[1] as u64),
+                min_size,
+                min_size + p[3] as usize * 64,
+            ))
+        }
+        15 => Box::new(AdlerChunker::new(p[1] as usize + 1, mask(p[2] % 12 + 1))),
+        16 => {
+            let min_exp = 5 + p[3] as u32 % 5;
+            Box::new(BuzhashChunker::new(
+                p[1] as usize % 31 + 1,
+                p[2] as u32,
+                p[4] as u32 % 16,
+                min_exp,
+                min_exp + 1 + p[5] as u32 % 4,
+            ))
+        }
+        17 => {
+            let mut table = [0u32; 256];
+            for (i, v) in table.iter_mut().enumerate() {
+                *v = (i as u32 ^ p[1] as u32).wrapping_mul(0x9e37_79b9);
+            }
+            let min_size = p[2] as usize + 1;
+            let avg = min_size + p[3] as usize * 4;
+            Box::new(CasyncChunker::new(
+                table,
+                min_size,
+                avg,
+                avg + p[4] as usize * 16,
+            ))
+        }
+        _ => {
+            let hash = RabinHash::new(Polynomial::generate(p[1] as u64), p[2] as usize % 32 + 1);
+            let min_size = p[3] as usize + 1;
+            Box::new(TTTDChunker::new(
+                hash,
+                min_size,
+                min_size + p[4] as usize * 4,
+                p[5] as u64 + 1,
+                p[6] as u64 + 1,
+            ))
+        }
+    }
+}
+
+/// A reader returning the data in parts of the given sizes, in turn.
+pub struct PartReader<'a> {
+    data: &'a [u8],
+    sizes: &'a [u8],
+    next: usize,
+}
+
+impl<'a> PartReader<'a> {
+    /// Each size is a byte plus one; with no sizes, everything is read at once.
+    pub fn new(data: &'a [u8], sizes: &'a [u8]) -> PartReader<'a> {
+        PartReader {
+            data,
+            sizes,
+            next: 0,
+        }
+    }
+}
+
+impl<'a> Read for PartReader<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let size = match self.sizes.get(self.next % self.sizes.len().max(1)) {
+            Some(&size) => size as usize + 1,
+            None => self.data.len(),
+        };
+        self.next += 1;
+        let size = min(min(size, buf.len()), self.data.len());
+        buf[..size].copy_from_slice(&self.data[..size]);
+        self.data = &self.data[size..];
+        Ok(size)
+    }
+}
+
+/// Gets the chunks from `slices()`.
+pub fn slices<I: ChunkerImpl>(chunker: Chunker<I>, data: &[u8]) -> Vec<Vec<u8>> {
+    chunker.slices(data).map(|c| c.to_vec()).collect()
+}
+
+/// Gets the chunks from `stream()`, reading parts of the given sizes.
+pub fn stream<I: ChunkerImpl>(
+    chunker: Chunker<I>,
+    data: &[u8],
+    sizes: &[u8],
+    capacity: usize,
+) -> Vec<Vec<u8>> {
+    let mut stream = chunker.stream_with_capacity(PartReader::new(data, sizes), capacity);
+    let mut chunks = Vec::new();
+    let mut chunk = Vec::new();
+    while let Some(input) = stream.read() {
+        match input.unwrap() {
+            ChunkInput::Data(d) => chunk.extend_from_slice(d),
+            ChunkInput::End => chunks.push(std::mem::take(&mut chunk)),
+        }
+    }
+    assert!(chunk.is_empty(), "data after the last End");
+    // The stream ends an empty input with an empty chunk
+    if data.is_empty() {
+        assert_eq!(chunks, [Vec::<u8>::new()]);
+        chunks.clear();
+    }
+    chunks
+}
+
+/// Checks that the chunks make up the data, and are not empty or too large.
+pub fn check_chunks(data: &[u8], chunks: &[Vec<u8>], max_size: Option<usize>) {
+    assert!(chunks.iter().all(|c| !c.is_empty()), "empty chunk");
+    if let Some(max_size) = max_size {
+        assert!(
+            chunks.iter().all(|c| c.len() <= max_size),
+            "chunk larger than max_size"
+        );
+    }
+    assert_eq!(chunks.concat(), data, "chunks don't make up the data");
+}
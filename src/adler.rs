@@ -0,0 +1,187 @@
+use ChunkerImpl;
+
+/// The rolling weak checksum used by rsync, a variant of Adler-32.
+///
+/// This keeps two 16-bit sums over a window of bytes: `s1` is the sum of the bytes, and `s2` is
+/// the sum of the successive values of `s1`. The window is not stored, so rolling requires the
+/// byte that leaves the window to be given back. This makes it usable both for chunking and for
+/// matching blocks of a known size, like rsync does.
+///
+/// Source: Andrew Tridgell and Paul Mackerras. "The rsync algorithm." Technical Report TR-CS-96-05,
+/// Australian National University, 1996.
+/// https://rsync.samba.org/tech_report/
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RollingAdler32 {
+    s1: u32,
+    s2: u32,
+    len: usize,
+}
+
+impl RollingAdler32 {
+    /// Creates the checksum of an empty window.
+    pub fn new() -> RollingAdler32 {
+        Default::default()
+    }
+
+    /// Creates the checksum of the given window.
+    pub fn from_window(data: &[u8]) -> RollingAdler32 {
+        let mut checksum = RollingAdler32::new();
+        checksum.update(data);
+        checksum
+    }
+
+    /// Adds bytes at the end of the window, making it larger.
+    pub fn update(&mut self, data: &[u8]) {
+        for &b in data {
+            self.push(b);
+        }
+    }
+
+    /// Adds a byte at the end of the window, making it larger.
+    pub fn push(&mut self, b: u8) {
+        self.s1 = self.s1.wrapping_add(b as u32);
+        self.s2 = self.s2.wrapping_add(self.s1);
+        self.len += 1;
+    }
+
+    /// Slides the window by one byte, `old` being the first byte of the window.
+    pub fn roll(&mut self, old: u8, new: u8) {
+        self.s1 = self.s1.wrapping_sub(old as u32).wrapping_add(new as u32);
+        self.s2 = self
+            .s2
+            .wrapping_sub((self.len as u32).wrapping_mul(old as u32))
+            .wrapping_add(self.s1);
+    }
+
+    /// Returns the size of the window.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if the window is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the checksum, `s1` in the low 16 bits and `s2` in the high 16 bits.
+    pub fn digest(&self) -> u32 {
+        (self.s1 & 0xffff) | (self.s2 << 16)
+    }
+}
+
+/// A chunker using rsync's rolling checksum over a fixed-size window.
+///
+/// A boundary is set after a byte if all the bits of `mask` are set in the checksum of the last
+/// `window_size` bytes. The average chunk size is 2^n for a mask with n bits set. No boundary is
+/// set before the window is full.
+///
+/// Requiring the bits to be set rather than unset prevents runs of zeros from being cut into
+/// minimum-size chunks, since their checksum is zero.
+#[derive(Debug, Clone)]
+pub struct AdlerChunker {
+    mask: u32,
+    window: Vec<u8>,
+    wpos: usize,
+    checksum: RollingAdler32,
+}
+
+impl AdlerChunker {
+    /// Creates a new rsync checksum chunker.
+    pub fn new(window_size: usize, mask: u32) -> AdlerChunker {
+        assert!(window_size > 0, "window_size needs to be at least 1");
+        AdlerChunker {
+            mask,
+            window: vec![0; window_size],
+            wpos: 0,
+            checksum: RollingAdler32::new(),
+        }
+    }
+}
+
+impl ChunkerImpl for AdlerChunker {
+    fn find_boundary(&mut self, data: &[u8]) -> Option<usize> {
+        for (i, &b) in data.iter().enumerate() {
+            if self.checksum.len() < self.window.len() {
+                self.checksum.push(b);
+            } else {
+                self.checksum.roll(self.window[self.wpos], b);
+            }
+            self.window[self.wpos] = b;
+            self.wpos += 1;
+            if self.wpos == self.window.len() {
+                self.wpos = 0;
+            }
+
+            if self.checksum.len() == self.window.len()
+                && self.checksum.digest() & self.mask == self.mask
+            {
+                return Some(i);
+            }
+        }
+
+        // No cut-point found within this block of data.
+        None
+    }
+
+    fn reset(&mut self) {
+        self.wpos = 0;
+        self.checksum = RollingAdler32::new();
+    }
+
+    fn window_size(&self) -> Option<usize> {
+        Some(self.window.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::{AdlerChunker, RollingAdler32};
+    use Chunker;
+
+    #[test]
+    fn test_rolling() {
+        let mut data = [0u8; 300];
+        StdRng::seed_from_u64(7).fill(&mut data[..]);
+
+        let mut rolling = RollingAdler32::from_window(&data[..32]);
+        for i in 32..data.len() {
+            rolling.roll(data[i - 32], data[i]);
+            let direct = RollingAdler32::from_window(&data[i - 31..i + 1]);
+            assert_eq!(rolling.digest(), direct.digest());
+        }
+
+        // rsync's checksum of "abcd", with s1 = 394 and s2 = 4 * 97 + 3 * 98 + 2 * 99 + 100
+        assert_eq!(
+            RollingAdler32::from_window(b"abcd").digest(),
+            394 | 980 << 16
+        );
+    }
+
+    #[test]
+    fn test_chunks() {
+        let mut data = vec![0u8; 1 << 20];
+        StdRng::seed_from_u64(8).fill(&mut data[..]);
+        let chunker = || AdlerChunker::new(64, 0x0fff_0000);
+
+        let expected: Vec<_> = Chunker::new(chunker())
+            .slices(&data)
+            .map(|c| c.len())
+            .collect();
+        assert!(expected.iter().all(|&l| l >= 64));
+        let average = data.len() / expected.len();
+        assert!(3000 < average && average < 5500);
+
+        let result: Vec<_> = Chunker::new(chunker())
+            .chunks(&data[..])
+            .map(|c| c.unwrap().length())
+            .collect();
+        assert_eq!(result, expected);
+
+        // Zeros don't produce boundaries
+        let zeros = vec![0u8; 10000];
+        assert_eq!(Chunker::new(chunker()).slices(&zeros).count(), 1);
+    }
+}
@@ -0,0 +1,138 @@
+//! Measuring how well a chunking method deduplicates some data.
+//!
+//! `DedupAnalysis` chunks a set of files, for example successive versions of
+//! the same file, and tracks the digests of the chunks it has seen, giving the
+//! amount of data that would actually need to be stored. This allows comparing
+//! chunking methods and parameters on real data.
+
+use std::collections::HashSet;
+use std::io::{self, Read};
+
+use {ChunkHasher, ChunkStats, Chunker, ChunkerImpl};
+
+/// Deduplication statistics over a set of files.
+#[derive(Debug, Default, Clone)]
+pub struct DedupAnalysis {
+    seen: HashSet<Vec<u8>>,
+    files: usize,
+    unique_bytes: u64,
+    stats: ChunkStats,
+}
+
+impl DedupAnalysis {
+    pub fn new() -> DedupAnalysis {
+        Default::default()
+    }
+
+    /// Chunks a file, adding its chunks to the statistics.
+    ///
+    /// Identical chunks are only detected through their digests, so `hasher`
+    /// should be collision-resistant, like SHA-256 or BLAKE3, and the same for
+    /// all the files.
+    pub fn add_reader<I, R, H>(
+        &mut self,
+        chunker: Chunker<I>,
+        reader: R,
+        hasher: H,
+    ) -> io::Result<()>
+    where
+        I: ChunkerImpl,
+        R: Read,
+        H: ChunkHasher,
+    {
+        for chunk in chunker.hashed_chunks(reader, hasher) {
+            let (info, digest) = chunk?;
+            self.stats.add(info.length());
+            if self.seen.insert(digest.as_ref().to_owned()) {
+                self.unique_bytes += info.length() as u64;
+            }
+        }
+        self.files += 1;
+        Ok(())
+    }
+
+    /// The number of files added.
+    pub fn files(&self) -> usize {
+        self.files
+    }
+
+    /// The total size of the files.
+    pub fn total_bytes(&self) -> u64 {
+        self.stats.total_length()
+    }
+
+    /// The size of the distinct chunks, i.e. what would need to be stored.
+    pub fn unique_bytes(&self) -> u64 {
+        self.unique_bytes
+    }
+
+    /// The number of chunks in all the files.
+    pub fn chunks(&self) -> u64 {
+        self.stats.count()
+    }
+
+    /// The number of distinct chunks.
+    pub fn unique_chunks(&self) -> u64 {
+        self.seen.len() as u64
+    }
+
+    /// The total size divided by the unique size; 1.0 means no deduplication.
+    pub fn dedup_ratio(&self) -> f64 {
+        if self.unique_bytes == 0 {
+            1.0
+        } else {
+            self.total_bytes() as f64 / self.unique_bytes as f64
+        }
+    }
+
+    /// The number of chunks by size: element `i` is the number of chunks of
+    /// size `2^i` to `2^(i+1) - 1`. Empty chunks are counted in the first one.
+    ///
+    /// This is the same as `chunk_stats().histogram()`.
+    pub fn histogram(&self) -> Vec<u64> {
+        self.stats.histogram()
+    }
+
+    /// Statistics on the size of all the chunks, including duplicates.
+    pub fn chunk_stats(&self) -> &ChunkStats {
+        &self.stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::DedupAnalysis;
+    use hash::tests::Fnv;
+    use {Chunker, FastCDC, FixedSizeChunker};
+
+    #[test]
+    fn test_analysis() {
+        let mut data = vec![0u8; 1 << 16];
+        StdRng::seed_from_u64(17).fill(&mut data[..]);
+        let mut modified = data.clone();
+        modified.splice(100..100, vec![1, 2, 3]);
+
+        let mut cdc = DedupAnalysis::new();
+        let mut fixed = DedupAnalysis::new();
+        for file in &[&data, &data, &modified] {
+            let chunker = Chunker::new(FastCDC::new(256, 1024, 4096));
+            cdc.add_reader(chunker, &file[..], Fnv::new()).unwrap();
+            let chunker = Chunker::new(FixedSizeChunker::new(1024));
+            fixed.add_reader(chunker, &file[..], Fnv::new()).unwrap();
+        }
+
+        assert_eq!(cdc.files(), 3);
+        assert_eq!(cdc.total_bytes(), 3 * data.len() as u64 + 3);
+        assert_eq!(cdc.histogram().iter().sum::<u64>(), cdc.chunks());
+        // Identical files are deduplicated, the modified one mostly
+        assert!(cdc.dedup_ratio() > 2.8);
+        assert!(cdc.unique_chunks() < cdc.chunks() / 2);
+        // Fixed-size chunks all shift
+        assert_eq!(fixed.unique_bytes(), 2 * data.len() as u64 + 3);
+        assert_eq!(fixed.histogram()[10], 64 * 3);
+        assert_eq!(fixed.histogram(), fixed.chunk_stats().histogram());
+    }
+}
@@ -0,0 +1,279 @@
+// This feature requires a more recent compiler than the rest of the crate
+#![allow(clippy::incompatible_msrv)]
+
+use std::future::Future;
+use std::io;
+use std::mem::swap;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_core::Stream;
+use tokio::io::{AsyncRead, ReadBuf};
+
+use {ChunkInfo, ChunkInput, ChunkerImpl, Splitter, BUF_SIZE};
+
+/// Asynchronous version of `ChunkStream`, reading from a tokio `AsyncRead`.
+///
+/// This is created by `Chunker::async_stream()`.
+pub struct AsyncChunkStream<R: AsyncRead + Unpin, I: ChunkerImpl> {
+    reader: R,
+    buffer: [u8; BUF_SIZE],
+    splitter: Splitter<I>,
+}
+
+impl<R: AsyncRead + Unpin, I: ChunkerImpl> AsyncChunkStream<R, I> {
+    pub(crate) fn new(reader: R, inner: I) -> AsyncChunkStream<R, I> {
+        AsyncChunkStream {
+            reader,
+            buffer: [0u8; BUF_SIZE],
+            splitter: Splitter::new(inner),
+        }
+    }
+
+    /// Reads more data into the buffer if needed.
+    fn poll_fill(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while self.splitter.needs_read() {
+            let mut buf = ReadBuf::new(&mut self.buffer);
+            match Pin::new(&mut self.reader).poll_read(cx, &mut buf) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Ready(Ok(())) => {
+                    self.splitter.filled(buf.filled());
+                    if buf.filled().is_empty() {
+                        break;
+                    }
+                }
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    /// Attempts to get the next `ChunkInput` item, like `ChunkStream::read()`.
+    pub fn poll_read(&mut self, cx: &mut Context<'_>) -> Poll<Option<io::Result<ChunkInput<'_>>>> {
+        match self.poll_fill(cx) {
+            Poll::Pending => Poll::Pending,
+            Poll::Ready(Err(e)) => Poll::Ready(Some(Err(e))),
+            Poll::Ready(Ok(())) => Poll::Ready(self.splitter.step(&self.buffer).map(Ok)),
+        }
+    }
+
+    /// Returns a future resolving to the next `ChunkInput` item.
+    pub fn read(&mut self) -> ReadChunkInput<'_, R, I> {
+        ReadChunkInput { stream: Some(self) }
+    }
+}
+
+/// Future returned by `AsyncChunkStream::read()`.
+pub struct ReadChunkInput<'a, R: AsyncRead + Unpin + 'a, I: ChunkerImpl + 'a> {
+    stream: Option<&'a mut AsyncChunkStream<R, I>>,
+}
+
+impl<'a, R: AsyncRead + Unpin, I: ChunkerImpl> Future for ReadChunkInput<'a, R, I> {
+    type Output = Option<io::Result<ChunkInput<'a>>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let stream = self.stream.take().expect("polled after completion");
+        match stream.poll_fill(cx) {
+            Poll::Pending => {
+                self.stream = Some(stream);
+                Poll::Pending
+            }
+            Poll::Ready(Err(e)) => Poll::Ready(Some(Err(e))),
+            Poll::Ready(Ok(())) => Poll::Ready(stream.splitter.step(&stream.buffer).map(Ok)),
+        }
+    }
+}
+
+/// Asynchronous version of `WholeChunks`, a `Stream` of chunks read into new vectors.
+///
+/// This is created by `Chunker::async_whole_chunks()`.
+pub struct AsyncWholeChunks<R: AsyncRead + Unpin, I: ChunkerImpl> {
+    stream: AsyncChunkStream<R, I>,
+    buffer: Vec<u8>,
+}
+
+impl<R: AsyncRead + Unpin, I: ChunkerImpl> AsyncWholeChunks<R, I> {
+    pub(crate) fn new(stream: AsyncChunkStream<R, I>) -> AsyncWholeChunks<R, I> {
+        AsyncWholeChunks {
+            stream,
+            buffer: Vec::new(),
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin, I: ChunkerImpl + Unpin> Stream for AsyncWholeChunks<R, I> {
+    type Item = io::Result<Vec<u8>>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            match this.stream.poll_read(cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(e))),
+                Poll::Ready(Some(Ok(ChunkInput::Data(d)))) => this.buffer.extend_from_slice(d),
+                Poll::Ready(Some(Ok(ChunkInput::End))) => {
+                    let mut res = Vec::new();
+                    swap(&mut res, &mut this.buffer);
+                    return Poll::Ready(Some(Ok(res)));
+                }
+            }
+        }
+    }
+}
+
+/// Asynchronous version of `ChunkInfoStream`, a `Stream` of chunk positions.
+///
+/// This is created by `Chunker::async_chunks()`.
+pub struct AsyncChunkInfoStream<R: AsyncRead + Unpin, I: ChunkerImpl> {
+    stream: AsyncChunkStream<R, I>,
+    last_chunk: usize,
+    pos: usize,
+}
+
+impl<R: AsyncRead + Unpin, I: ChunkerImpl> AsyncChunkInfoStream<R, I> {
+    pub(crate) fn new(stream: AsyncChunkStream<R, I>) -> AsyncChunkInfoStream<R, I> {
+        AsyncChunkInfoStream {
+            stream,
+            last_chunk: 0,
+            pos: 0,
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin, I: ChunkerImpl + Unpin> Stream for AsyncChunkInfoStream<R, I> {
+    type Item = io::Result<ChunkInfo>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            match this.stream.poll_read(cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(e))),
+                Poll::Ready(Some(Ok(ChunkInput::Data(d)))) => this.pos += d.len(),
+                Poll::Ready(Some(Ok(ChunkInput::End))) => {
+                    let start = this.last_chunk;
+                    this.last_chunk = this.pos;
+                    return Poll::Ready(Some(Ok(ChunkInfo {
+                        start,
+                        length: this.pos - start,
+                    })));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_core::Stream;
+    use rand::{self, Rng};
+    use std::future::Future;
+    use std::io;
+    use std::pin::Pin;
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Wake, Waker};
+    use tokio::io::{AsyncRead, ReadBuf};
+
+    use {ChunkInput, Chunker, GearChunker};
+
+    struct NoopWaker;
+
+    impl Wake for NoopWaker {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    /// Polls a future until it is ready; the readers here never wait on anything.
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let waker = Waker::from(Arc::new(NoopWaker));
+        let mut cx = Context::from_waker(&waker);
+        let mut future = Box::pin(future);
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+        }
+    }
+
+    /// Collects the items of a stream, polling it in the same way.
+    fn collect<S: Stream + Unpin>(mut stream: S) -> Vec<S::Item> {
+        let waker = Waker::from(Arc::new(NoopWaker));
+        let mut cx = Context::from_waker(&waker);
+        let mut items = Vec::new();
+        loop {
+            match Pin::new(&mut stream).poll_next(&mut cx) {
+                Poll::Ready(Some(item)) => items.push(item),
+                Poll::Ready(None) => return items,
+                Poll::Pending => {}
+            }
+        }
+    }
+
+    /// A reader returning few bytes at a time, and `Pending` every other time.
+    struct SlowReader<'a> {
+        data: &'a [u8],
+        pending: bool,
+    }
+
+    impl<'a> AsyncRead for SlowReader<'a> {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<io::Result<()>> {
+            self.pending = !self.pending;
+            if self.pending {
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            let len = [self.data.len(), buf.remaining(), 3]
+                .iter()
+                .cloned()
+                .min()
+                .unwrap();
+            buf.put_slice(&self.data[..len]);
+            self.data = &self.data[len..];
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    #[test]
+    fn test_async() {
+        let mut data = vec![0u8; 1 << 14];
+        rand::thread_rng().fill(&mut data[..]);
+        let chunker = || Chunker::new(GearChunker::new(0xfc00_0000));
+        let reader = || SlowReader {
+            data: &data,
+            pending: false,
+        };
+
+        let expected: Vec<_> = chunker()
+            .whole_chunks(&data[..])
+            .map(|c| c.unwrap())
+            .collect();
+
+        let result: Vec<_> = collect(chunker().async_whole_chunks(reader()))
+            .into_iter()
+            .map(|c| c.unwrap())
+            .collect();
+        assert_eq!(result, expected);
+
+        let result: Vec<_> = collect(chunker().async_chunks(reader()))
+            .into_iter()
+            .map(|c| c.unwrap().length())
+            .collect();
+        assert_eq!(result, expected.iter().map(|c| c.len()).collect::<Vec<_>>());
+
+        let mut stream = chunker().async_stream(reader());
+        let mut result = vec![Vec::new()];
+        while let Some(input) = block_on(stream.read()) {
+            match input.unwrap() {
+                ChunkInput::Data(d) => result.last_mut().unwrap().extend_from_slice(d),
+                ChunkInput::End => result.push(Vec::new()),
+            }
+        }
+        assert_eq!(result.pop(), Some(Vec::new()));
+        assert_eq!(result, expected);
+    }
+}
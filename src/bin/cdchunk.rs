@@ -0,0 +1,938 @@
+//! Command-line tool printing the chunks of files, or how well they deduplicate.
+
+#[cfg(feature = "blake3")]
+extern crate blake3;
+extern crate cdchunking;
+extern crate sha2;
+#[cfg(feature = "xxhash")]
+extern crate xxhash_rust;
+
+use std::collections::HashMap;
+use std::env;
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::process;
+use std::str::FromStr;
+
+use cdchunking::analysis::DedupAnalysis;
+use cdchunking::{
+    AEChunker, AdlerChunker, BFBCChunker, BuzhashChunker, CasyncChunker, ChunkHasher, ChunkInfo,
+    Chunker, ChunkerImpl, FastCDC, FastCDC2020, FixedSizeChunker, GearChunker, MIIChunker,
+    MinSizeLimited, NormalizedChunkingGearChunker, PCIChunker, Polynomial, RAMChunker,
+    RabinChunker, RabinHash, ResticChunker, SizeLimited, TTTDChunker, ZPAQCompatible, ZPAQ,
+};
+
+const USAGE: &str = "\
+Usage: cdchunk [OPTIONS] [FILE...]
+       cdchunk analyze [OPTIONS] [FILE...]
+
+Prints the offset and length of the chunks of each file (or standard input).
+
+With analyze, prints deduplication statistics over all the files instead: the
+number of chunks, the size of the distinct chunks, and a histogram of chunk
+sizes. Chunks are identified by their digest, SHA-256 by default.
+
+Options:
+  --algo NAME       Chunking algorithm (default: fastcdc), see below
+  --min SIZE        Minimum chunk size
+  --max SIZE        Maximum chunk size
+  --format FORMAT   Output format: text (default), csv or json
+  --digest HASH     Also print the digest of each chunk: sha256, blake3, xxh3
+  -h, --help        Print this message
+
+Sizes can use the suffixes K, M and G (powers of 1024).
+
+Algorithms and their parameters:
+  gear              --bits N: average size of 2^N (default: 13)
+  normalized-gear   --bits N: average size of 2^N, from 3 to 29 (default: 13)
+  fastcdc           --avg SIZE (default: 8K); --min and --max default to
+  fastcdc2020         avg/4 and avg*8, and are part of the algorithm
+  zpaq              --bits N: average size of 2^N (default: 13)
+  zpaq-compatible   --fragment N: from 0 to 22, like zpaq's (default: 6)
+  ae                --window SIZE (required)
+  ram               --window SIZE (required)
+  mii               --threshold N: interval length (required)
+  pci               --window N: from 1 to 16 bytes (required)
+                    --threshold N: number of one bits (required)
+  bfbc              --pairs AABB,CCDD,...: frequent byte pairs in hex
+                    (required), --min is part of the algorithm (required)
+  fixed             --size SIZE (default: 8K)
+  restic            --poly HEX: irreducible polynomial (required), --min and
+                    --max default to 512K and 8M and are part of the algorithm
+  rabin             --poly HEX: irreducible polynomial (required)
+                    --window SIZE (default: 64)
+                    --bits N: average size of 2^N (default: 13)
+  adler             --window SIZE (default: 64)
+                    --bits N: average size of 2^N (default: 13)
+  buzhash           --window SIZE (default: 4095), --seed N (default: 0)
+                    --bits N: average size of 2^N (default: 21)
+                    --table FILE: 256 numbers in hex (default: not borg's)
+                    --min and --max are powers of 2 and part of the
+                    algorithm (default: 512K and 8M)
+  casync            --table FILE: casync's 256 numbers in hex (required)
+                    --avg SIZE (default: 64K); --min and --max default to
+                    avg/4 and avg*4, and are part of the algorithm
+  tttd              --poly HEX: irreducible polynomial (required)
+                    --window SIZE (default: 48)
+                    --avg N: main divisor, the backup one being half of it
+                    (default: 540); --min and --max default to 460 and
+                    2800, and are part of the algorithm
+
+For other algorithms, --min and --max wrap the chunker, see Chunker::min_size()
+and Chunker::max_size().
+";
+
+/// Options that take a value.
+const OPTIONS: &[&str] = &[
+    "algo",
+    "min",
+    "max",
+    "format",
+    "digest",
+    "bits",
+    "avg",
+    "window",
+    "threshold",
+    "pairs",
+    "size",
+    "poly",
+    "fragment",
+    "seed",
+    "table",
+];
+
+struct Args {
+    options: HashMap<String, String>,
+    files: Vec<String>,
+}
+
+impl Args {
+    fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Args, String> {
+        let mut options = HashMap::new();
+        let mut files = Vec::new();
+        while let Some(arg) = args.next() {
+            if arg == "-h" || arg == "--help" {
+                print!("{}", USAGE);
+                process::exit(0);
+            } else if arg == "--" {
+                files.extend(args.by_ref());
+            } else if let Some(option) = arg.strip_prefix("--") {
+                let (name, value) = match option.find('=') {
+                    Some(pos) => (option[..pos].to_owned(), option[pos + 1..].to_owned()),
+                    None => {
+                        let value = args
+                            .next()
+                            .ok_or_else(|| format!("Missing value for {}", arg))?;
+                        (option.to_owned(), value)
+                    }
+                };
+                if !OPTIONS.contains(&&name[..]) {
+                    return Err(format!("Unknown option --{}", name));
+                }
+                options.insert(name, value);
+            } else {
+                files.push(arg);
+            }
+        }
+        Ok(Args { options, files })
+    }
+
+    fn get(&self, name: &str) -> Option<&str> {
+        self.options.get(name).map(|v| &v[..])
+    }
+
+    fn size(&self, name: &str) -> Result<Option<usize>, String> {
+        match self.get(name) {
+            Some(value) => parse_size(value)
+                .map(Some)
+                .ok_or_else(|| format!("Invalid --{}", name)),
+            None => Ok(None),
+        }
+    }
+
+    fn required<T: FromStr>(&self, name: &str, algo: &str) -> Result<T, String> {
+        match self.get(name) {
+            Some(value) => value.parse().map_err(|_| format!("Invalid --{}", name)),
+            None => Err(format!("{} needs --{}", algo, name)),
+        }
+    }
+
+    fn required_size(&self, name: &str, algo: &str) -> Result<usize, String> {
+        self.size(name)?
+            .ok_or_else(|| format!("{} needs --{}", algo, name))
+    }
+}
+
+/// Parses a size, with an optional K, M or G suffix.
+fn parse_size(value: &str) -> Option<usize> {
+    let (number, shift) = match value.chars().last() {
+        Some('K') | Some('k') => (&value[..value.len() - 1], 10),
+        Some('M') | Some('m') => (&value[..value.len() - 1], 20),
+        Some('G') | Some('g') => (&value[..value.len() - 1], 30),
+        _ => (value, 0),
+    };
+    number
+        .parse::<usize>()
+        .ok()
+        .and_then(|n| n.checked_mul(1 << shift))
+}
+
+/// Parses byte pairs given in hexadecimal, like `0000,6520`.
+fn parse_pairs(value: &str) -> Option<Vec<(u8, u8)>> {
+    value
+        .split(',')
+        .map(|pair| {
+            if pair.len() != 4 {
+                return None;
+            }
+            match (
+                u8::from_str_radix(&pair[..2], 16),
+                u8::from_str_radix(&pair[2..], 16),
+            ) {
+                (Ok(a), Ok(b)) => Some((a, b)),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Parses the `--poly` option, an irreducible polynomial in hexadecimal.
+fn parse_poly(args: &Args, algo: &str) -> Result<Polynomial, String> {
+    let poly = args
+        .get("poly")
+        .ok_or_else(|| format!("{} needs --poly", algo))?;
+    let poly = u64::from_str_radix(poly.trim_start_matches("0x"), 16)
+        .map_err(|_| "Invalid --poly".to_owned())?;
+    let poly = Polynomial(poly);
+    if poly.degree().map_or(true, |d| !(8..=56).contains(&d)) {
+        return Err("--poly needs to be of degree 8 to 56".to_owned());
+    }
+    if !poly.is_irreducible() {
+        return Err("--poly needs to be irreducible".to_owned());
+    }
+    Ok(poly)
+}
+
+/// Reads a Buzhash table, 256 numbers in hexadecimal separated by spaces or
+/// commas.
+fn read_table(path: &str) -> Result<[u32; 256], String> {
+    let mut text = String::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut text))
+        .map_err(|e| format!("Can't read {}: {}", path, e))?;
+    let values = text
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|v| !v.is_empty())
+        .map(|v| u32::from_str_radix(v.trim_start_matches("0x"), 16))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| format!("Invalid number in {}", path))?;
+    if values.len() != 256 {
+        return Err(format!("{} needs to have 256 numbers", path));
+    }
+    let mut table = [0u32; 256];
+    table.copy_from_slice(&values);
+    Ok(table)
+}
+
+fn pci(window: usize, threshold: u32) -> Option<Box<dyn ChunkerImpl>> {
+    macro_rules! pci {
+        ($($w:expr),*) => {
+            match window {
+                $($w => Some(Box::new(PCIChunker::<$w>::new(threshold))),)*
+                _ => None,
+            }
+        };
+    }
+    pci!(1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16)
+}
+
+/// Creates the chunking method from the options.
+fn make_chunker(args: &Args) -> Result<Box<dyn ChunkerImpl>, String> {
+    let algo = args.get("algo").unwrap_or("fastcdc");
+    let bits = |default| match args.get("bits") {
+        Some(b) => match b.parse::<u32>() {
+            Ok(b) if (1..=31).contains(&b) => Ok(b),
+            _ => Err("Invalid --bits".to_owned()),
+        },
+        None => Ok(default),
+    };
+    let (min, max) = (args.size("min")?, args.size("max")?);
+    if min == Some(0) {
+        return Err("Invalid --min".to_owned());
+    }
+    if max == Some(0) {
+        return Err("Invalid --max".to_owned());
+    }
+    let window_or = |default| match args.size("window")?.unwrap_or(default) {
+        0 => Err("Invalid --window".to_owned()),
+        w => Ok(w),
+    };
+
+    // Algorithms that handle --min and --max themselves
+    match algo {
+        "fastcdc" | "fastcdc2020" => {
+            let avg = args.size("avg")?.unwrap_or(8192);
+            // Range allowed by FastCDC::new(), which uses normalization level 2
+            if avg < 8 || avg as u64 >= 1 << 47 {
+                return Err("--avg needs to be from 8 to 2^47 - 1".to_owned());
+            }
+            let min = min.unwrap_or(avg / 4);
+            let max = max.unwrap_or(avg * 8);
+            if !(min <= avg && avg <= max) {
+                return Err("Sizes need to be --min <= --avg <= --max".to_owned());
+            }
+            return Ok(if algo == "fastcdc" {
+                Box::new(FastCDC::new(min, avg, max))
+            } else {
+                Box::new(FastCDC2020::new(min, avg, max))
+            });
+        }
+        "bfbc" => {
+            let pairs = args
+                .get("pairs")
+                .ok_or_else(|| "bfbc needs --pairs".to_owned())?;
+            let pairs = parse_pairs(pairs).ok_or_else(|| "Invalid --pairs".to_owned())?;
+            let min = args.required_size("min", algo)?;
+            if min < 2 {
+                return Err("bfbc needs --min of at least 2".to_owned());
+            }
+            let chunker = BFBCChunker::new(pairs, min);
+            return Ok(match max {
+                Some(max) => Box::new(SizeLimited::new(chunker, max)),
+                None => Box::new(chunker),
+            });
+        }
+        "restic" => {
+            let poly = parse_poly(args, algo)?;
+            let min = min.unwrap_or(512 * 1024);
+            let max = max.unwrap_or(8 * 1024 * 1024);
+            if min < 64 {
+                return Err("restic needs --min of at least 64 (the window size)".to_owned());
+            }
+            if min > max {
+                return Err("Sizes need to be --min <= --max".to_owned());
+            }
+            return Ok(Box::new(ResticChunker::with_boundaries(poly, min, max)));
+        }
+        "buzhash" => {
+            let window = window_or(4095)?;
+            let seed = match args.get("seed") {
+                Some(seed) => seed.parse().map_err(|_| "Invalid --seed".to_owned())?,
+                None => 0,
+            };
+            let exp = |size: usize, name| {
+                if size.is_power_of_two() {
+                    Ok(size.trailing_zeros())
+                } else {
+                    Err(format!("buzhash needs --{} to be a power of 2", name))
+                }
+            };
+            let min_exp = exp(min.unwrap_or(512 * 1024), "min")?;
+            let max_exp = exp(max.unwrap_or(8 * 1024 * 1024), "max")?;
+            if (1usize << min_exp)
+                .checked_add(window)
+                .map_or(true, |size| size >= 1 << max_exp)
+            {
+                return Err("buzhash needs --max to be more than --min plus --window".to_owned());
+            }
+            let mask_bits = bits(21)?;
+            return Ok(match args.get("table") {
+                Some(path) => Box::new(BuzhashChunker::with_table(
+                    read_table(path)?,
+                    window,
+                    seed,
+                    mask_bits,
+                    min_exp,
+                    max_exp,
+                )),
+                None => Box::new(BuzhashChunker::new(
+                    window, seed, mask_bits, min_exp, max_exp,
+                )),
+            });
+        }
+        "casync" => {
+            let path = args
+                .get("table")
+                .ok_or_else(|| "casync needs --table".to_owned())?;
+            let table = read_table(path)?;
+            let avg = args.size("avg")?.unwrap_or(64 * 1024);
+            let min = min.unwrap_or((avg / 4).max(1));
+            let max = match max {
+                Some(max) => max,
+                None => avg
+                    .checked_mul(4)
+                    .ok_or_else(|| "Invalid --avg".to_owned())?,
+            };
+            if !(min <= avg && avg <= max) {
+                return Err("Sizes need to be --min <= --avg <= --max".to_owned());
+            }
+            return Ok(Box::new(CasyncChunker::new(table, min, avg, max)));
+        }
+        "tttd" => {
+            let poly = parse_poly(args, algo)?;
+            let window = window_or(48)?;
+            let divisor = match args.size("avg")?.unwrap_or(540) {
+                0 => return Err("Invalid --avg".to_owned()),
+                d => d as u64,
+            };
+            let min = min.unwrap_or(460);
+            let max = max.unwrap_or(2800);
+            if min > max {
+                return Err("Sizes need to be --min <= --max".to_owned());
+            }
+            let hash = RabinHash::new(poly, window);
+            let backup = (divisor / 2).max(1);
+            return Ok(Box::new(TTTDChunker::new(hash, min, max, divisor, backup)));
+        }
+        _ => {}
+    }
+
+    let window = || match args.required_size("window", algo)? {
+        0 => Err("Invalid --window".to_owned()),
+        w => Ok(w),
+    };
+    let chunker: Box<dyn ChunkerImpl> = match algo {
+        "gear" => {
+            let bits = bits(13)?;
+            Box::new(GearChunker::new(!0u32 << (32 - bits)))
+        }
+        "normalized-gear" => {
+            let bits = bits(13)?;
+            if !(3..=29).contains(&bits) {
+                return Err("normalized-gear needs --bits from 3 to 29".to_owned());
+            }
+            // Like FastCDC, 2 bits more before the average size and 2 less after
+            Box::new(NormalizedChunkingGearChunker::new(
+                !0u32 << (32 - (bits + 2)),
+                !0u32 << (32 - (bits - 2)),
+                1 << bits,
+            ))
+        }
+        "zpaq" => Box::new(ZPAQ::new(bits(13)? as usize)),
+        "zpaq-compatible" => {
+            let fragment = match args.get("fragment") {
+                Some(f) => f
+                    .parse()
+                    .ok()
+                    .filter(|&f| f <= 22)
+                    .ok_or_else(|| "Invalid --fragment".to_owned())?,
+                None => 6,
+            };
+            Box::new(ZPAQCompatible::new(fragment))
+        }
+        "rabin" => {
+            let poly = parse_poly(args, algo)?;
+            let mask = (1 << bits(13)?) - 1;
+            Box::new(RabinChunker::new(poly, window_or(64)?, mask, 0))
+        }
+        "adler" => Box::new(AdlerChunker::new(window_or(64)?, (1 << bits(13)?) - 1)),
+        "ae" => Box::new(AEChunker::new(window()?)),
+        "ram" => Box::new(RAMChunker::new(window()?)),
+        "mii" => Box::new(MIIChunker::new(args.required("threshold", algo)?)),
+        "pci" => {
+            let window = args.required_size("window", algo)?;
+            pci(window, args.required("threshold", algo)?)
+                .ok_or_else(|| "Invalid --window for pci".to_owned())?
+        }
+        "fixed" => {
+            let size = args.size("size")?.unwrap_or(8192);
+            if size == 0 {
+                return Err("Invalid --size".to_owned());
+            }
+            Box::new(FixedSizeChunker::new(size))
+        }
+        _ => return Err(format!("Unknown algorithm {}", algo)),
+    };
+    let chunker: Box<dyn ChunkerImpl> = match min {
+        Some(min) => Box::new(MinSizeLimited::new(chunker, min)),
+        None => chunker,
+    };
+    Ok(match max {
+        Some(max) => Box::new(SizeLimited::new(chunker, max)),
+        None => chunker,
+    })
+}
+
+/// The hash function selected with `--digest`, if any.
+enum Hasher {
+    None,
+    Sha256(sha2::Sha256),
+    #[cfg(feature = "blake3")]
+    Blake3(Box<blake3::Hasher>),
+    #[cfg(feature = "xxhash")]
+    Xxh3(Box<xxhash_rust::xxh3::Xxh3>),
+}
+
+impl Hasher {
+    fn new(name: Option<&str>) -> Result<Hasher, String> {
+        Ok(match name {
+            None => Hasher::None,
+            Some("sha256") => Hasher::Sha256(Default::default()),
+            #[cfg(feature = "blake3")]
+            Some("blake3") => Hasher::Blake3(Box::new(blake3::Hasher::new())),
+            #[cfg(feature = "xxhash")]
+            Some("xxh3") => Hasher::Xxh3(Box::new(xxhash_rust::xxh3::Xxh3::new())),
+            Some(name) => return Err(format!("Unsupported digest {}", name)),
+        })
+    }
+}
+
+impl ChunkHasher for Hasher {
+    type Digest = Vec<u8>;
+
+    fn update(&mut self, data: &[u8]) {
+        match *self {
+            Hasher::None => {}
+            Hasher::Sha256(ref mut h) => ChunkHasher::update(h, data),
+            #[cfg(feature = "blake3")]
+            Hasher::Blake3(ref mut h) => ChunkHasher::update(&mut **h, data),
+            #[cfg(feature = "xxhash")]
+            Hasher::Xxh3(ref mut h) => ChunkHasher::update(&mut **h, data),
+        }
+    }
+
+    fn finish(&mut self) -> Vec<u8> {
+        match *self {
+            Hasher::None => Vec::new(),
+            Hasher::Sha256(ref mut h) => h.finish().to_vec(),
+            #[cfg(feature = "blake3")]
+            Hasher::Blake3(ref mut h) => ChunkHasher::finish(&mut **h).to_vec(),
+            #[cfg(feature = "xxhash")]
+            Hasher::Xxh3(ref mut h) => ChunkHasher::finish(&mut **h).to_vec(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Text,
+    Csv,
+    Json,
+}
+
+fn to_hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Quotes a string for JSON or CSV output.
+fn quote(s: &str, format: Format) -> String {
+    let mut quoted = String::from("\"");
+    for c in s.chars() {
+        match (c, format) {
+            ('"', Format::Csv) => quoted.push_str("\"\""),
+            ('"', Format::Json) => quoted.push_str("\\\""),
+            ('\\', Format::Json) => quoted.push_str("\\\\"),
+            (c, Format::Json) if (c as u32) < 0x20 => {
+                quoted.push_str(&format!("\\u{:04x}", c as u32))
+            }
+            (c, _) => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Reports an error writing the output.
+///
+/// If the output was closed, for example by piping into `head`, this exits
+/// quietly instead.
+fn write_error(e: io::Error) -> String {
+    if e.kind() == io::ErrorKind::BrokenPipe {
+        process::exit(0);
+    }
+    format!("Error writing output: {}", e)
+}
+
+/// Prints the chunks of one file.
+fn print_chunks<R: Read, W: Write>(
+    args: &Args,
+    name: &str,
+    reader: R,
+    format: Format,
+    first: &mut bool,
+    out: &mut W,
+) -> Result<(), String> {
+    let chunker = Chunker::new(make_chunker(args)?);
+    let hasher = Hasher::new(args.get("digest"))?;
+    let show_name = args.files.len() > 1;
+    for chunk in chunker.hashed_chunks(reader, hasher) {
+        let (info, digest) = chunk.map_err(|e| format!("Error reading {}: {}", name, e))?;
+        print_chunk(out, name, show_name, &info, &digest, format, *first).map_err(write_error)?;
+        *first = false;
+    }
+    Ok(())
+}
+
+/// Prints one chunk.
+fn print_chunk<W: Write>(
+    out: &mut W,
+    name: &str,
+    show_name: bool,
+    info: &ChunkInfo,
+    digest: &[u8],
+    format: Format,
+    first: bool,
+) -> io::Result<()> {
+    match format {
+        Format::Text => {
+            if show_name {
+                write!(out, "{} ", name)?;
+            }
+            write!(out, "{} {}", info.start(), info.length())?;
+            if !digest.is_empty() {
+                write!(out, " {}", to_hex(digest))?;
+            }
+            writeln!(out)
+        }
+        Format::Csv => {
+            write!(
+                out,
+                "{},{},{}",
+                quote(name, format),
+                info.start(),
+                info.length()
+            )?;
+            if !digest.is_empty() {
+                write!(out, ",{}", to_hex(digest))?;
+            }
+            writeln!(out)
+        }
+        Format::Json => {
+            write!(
+                out,
+                "{}\n  {{\"file\": {}, \"offset\": {}, \"length\": {}",
+                if first { "" } else { "," },
+                quote(name, format),
+                info.start(),
+                info.length()
+            )?;
+            if !digest.is_empty() {
+                write!(out, ", \"digest\": \"{}\"", to_hex(digest))?;
+            }
+            write!(out, "}}")
+        }
+    }
+}
+
+fn run(args: &Args) -> Result<(), String> {
+    let format = match args.get("format") {
+        None | Some("text") => Format::Text,
+        Some("csv") => Format::Csv,
+        Some("json") => Format::Json,
+        Some(f) => return Err(format!("Unknown format {}", f)),
+    };
+    // Check the options before reading anything
+    make_chunker(args)?;
+    Hasher::new(args.get("digest"))?;
+
+    let stdout = io::stdout();
+    let mut out = BufWriter::new(stdout.lock());
+    match format {
+        Format::Text => {}
+        Format::Csv => {
+            let digest = if args.get("digest").is_some() {
+                ",digest"
+            } else {
+                ""
+            };
+            writeln!(out, "file,offset,length{}", digest).map_err(write_error)?;
+        }
+        Format::Json => write!(out, "[").map_err(write_error)?,
+    }
+
+    let mut first = true;
+    if args.files.is_empty() {
+        let stdin = io::stdin();
+        print_chunks(args, "-", stdin.lock(), format, &mut first, &mut out)?;
+    }
+    for name in &args.files {
+        if name == "-" {
+            let stdin = io::stdin();
+            print_chunks(args, name, stdin.lock(), format, &mut first, &mut out)?;
+        } else {
+            let file = File::open(name).map_err(|e| format!("Can't open {}: {}", name, e))?;
+            print_chunks(args, name, file, format, &mut first, &mut out)?;
+        }
+    }
+
+    if format == Format::Json {
+        writeln!(out, "\n]").map_err(write_error)?;
+    }
+    out.flush().map_err(write_error)
+}
+
+/// Chunks all the files and prints deduplication statistics.
+fn analyze(args: &Args) -> Result<(), String> {
+    if args.get("format").map_or(false, |f| f != "text") {
+        return Err("analyze only supports the text format".to_owned());
+    }
+    let digest = args.get("digest").unwrap_or("sha256");
+    make_chunker(args)?;
+    Hasher::new(Some(digest))?;
+
+    let mut analysis = DedupAnalysis::new();
+    let mut add = |name: &str, reader: &mut dyn Read| {
+        let chunker = Chunker::new(make_chunker(args)?);
+        let hasher = Hasher::new(Some(digest))?;
+        analysis
+            .add_reader(chunker, reader, hasher)
+            .map_err(|e| format!("Error reading {}: {}", name, e))
+    };
+    if args.files.is_empty() {
+        let stdin = io::stdin();
+        add("-", &mut stdin.lock())?;
+    }
+    for name in &args.files {
+        if name == "-" {
+            let stdin = io::stdin();
+            add(name, &mut stdin.lock())?;
+        } else {
+            let mut file = File::open(name).map_err(|e| format!("Can't open {}: {}", name, e))?;
+            add(name, &mut file)?;
+        }
+    }
+
+    let stdout = io::stdout();
+    let mut out = BufWriter::new(stdout.lock());
+    print_analysis(&analysis, &mut out)
+        .and_then(|()| out.flush())
+        .map_err(write_error)
+}
+
+fn print_analysis<W: Write>(analysis: &DedupAnalysis, out: &mut W) -> io::Result<()> {
+    writeln!(out, "files:         {}", analysis.files())?;
+    writeln!(out, "total bytes:   {}", analysis.total_bytes())?;
+    writeln!(out, "unique bytes:  {}", analysis.unique_bytes())?;
+    writeln!(out, "chunks:        {}", analysis.chunks())?;
+    writeln!(out, "unique chunks: {}", analysis.unique_chunks())?;
+    writeln!(out, "dedup ratio:   {:.3}", analysis.dedup_ratio())?;
+    let stats = analysis.chunk_stats();
+    if let (Some(min), Some(max)) = (stats.min(), stats.max()) {
+        writeln!(out, "chunk size:    min {}, max {}", min, max)?;
+        writeln!(
+            out,
+            "               mean {:.1}, stddev {:.1}",
+            stats.mean(),
+            stats.stddev()
+        )?;
+        // Percentiles are approximate, see ChunkStats::percentile()
+        let percentile = |p| stats.percentile(p).unwrap();
+        writeln!(
+            out,
+            "               ~p10 {}, ~p50 {}, ~p90 {}",
+            percentile(10.0),
+            percentile(50.0),
+            percentile(90.0)
+        )?;
+        writeln!(out, "chunk sizes:")?;
+    }
+    for (i, &count) in stats.histogram().iter().enumerate() {
+        if count > 0 {
+            let low = if i == 0 { 0 } else { 1u64 << i };
+            let high = (1u64 << (i + 1)) - 1;
+            writeln!(out, "  {:>10} - {:<10} {}", low, high, count)?;
+        }
+    }
+    Ok(())
+}
+
+fn main() {
+    let mut args = env::args().skip(1).peekable();
+    let command = if args.peek().map(|a| &a[..]) == Some("analyze") {
+        args.next();
+        analyze
+    } else {
+        run
+    };
+    let result = Args::parse(args).and_then(|args| command(&args));
+    if let Err(e) = result {
+        eprintln!("cdchunk: {}", e);
+        eprintln!("Try 'cdchunk --help' for more information.");
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    use super::{
+        make_chunker, parse_pairs, parse_size, print_analysis, print_chunk, quote, read_table,
+        Args, Format, Hasher,
+    };
+    use cdchunking::analysis::DedupAnalysis;
+    use cdchunking::Chunker;
+
+    fn args(list: &[&str]) -> Args {
+        Args::parse(list.iter().map(|s| s.to_string())).unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse_size("100"), Some(100));
+        assert_eq!(parse_size("8K"), Some(8192));
+        assert_eq!(parse_size("2m"), Some(2 << 20));
+        assert_eq!(parse_size("K"), None);
+        assert_eq!(parse_pairs("0000,6520"), Some(vec![(0, 0), (0x65, 0x20)]));
+        assert_eq!(parse_pairs("000"), None);
+        assert_eq!(quote("a\"b", Format::Csv), "\"a\"\"b\"");
+        assert_eq!(quote("a\"b\n", Format::Json), "\"a\\\"b\\u000a\"");
+
+        let a = args(&["--algo=gear", "--max", "1K", "a", "--", "--b"]);
+        assert_eq!(a.get("algo"), Some("gear"));
+        assert_eq!(a.size("max"), Ok(Some(1024)));
+        assert_eq!(a.files, ["a", "--b"]);
+        assert!(Args::parse(vec!["--nope".to_owned(), "1".to_owned()].into_iter()).is_err());
+    }
+
+    #[test]
+    fn test_chunkers() {
+        let data: Vec<u8> = (0..100_000u64).map(|i| (i * i % 251) as u8).collect();
+        for list in &[
+            &["--algo", "fixed", "--size", "100"][..],
+            &["--algo", "gear", "--min", "64", "--max", "1K"],
+            &["--algo", "fastcdc", "--avg", "1K"],
+            &["--algo", "ae", "--window", "100"],
+            &["--algo", "pci", "--window", "5", "--threshold", "25"],
+            &["--algo", "bfbc", "--pairs", "0000,0102", "--min", "100"],
+            &["--algo", "normalized-gear", "--bits", "10"],
+            &["--algo", "zpaq-compatible", "--fragment", "0"],
+            &[
+                "--algo",
+                "rabin",
+                "--poly",
+                "3DA3358B4DC173",
+                "--bits",
+                "10",
+            ],
+            &["--algo", "adler", "--bits", "10"],
+            &[
+                "--algo", "buzhash", "--min", "1K", "--max", "8K", "--bits", "10",
+            ],
+            &["--algo", "tttd", "--poly", "3DA3358B4DC173"],
+        ] {
+            let chunker = make_chunker(&args(list)).unwrap();
+            let total: usize = Chunker::new(chunker).slices(&data).map(|c| c.len()).sum();
+            assert_eq!(total, data.len());
+        }
+        let chunker = make_chunker(&args(&["--algo", "fixed", "--size", "100"])).unwrap();
+        assert!(Chunker::new(chunker).slices(&data).all(|c| c.len() == 100));
+
+        assert!(make_chunker(&args(&["--algo", "nope"])).is_err());
+        assert!(make_chunker(&args(&["--algo", "ae"])).is_err());
+        assert!(make_chunker(&args(&["--algo", "pci", "--window", "17"])).is_err());
+        for list in &[
+            &["--avg", "3"][..],
+            &["--avg", "0"],
+            &["--avg", "1000000G"],
+            &["--min", "0", "--avg", "1K"],
+            &["--algo", "gear", "--min", "0"],
+            &["--algo", "gear", "--max", "0"],
+            &[
+                "--algo", "bfbc", "--pairs", "0000", "--min", "2", "--max", "0",
+            ],
+            &["--algo", "ae", "--window", "0"],
+            &["--algo", "ram", "--window", "0"],
+            &[
+                "--algo",
+                "restic",
+                "--poly",
+                "3DA3358B4DC173",
+                "--min",
+                "10",
+            ],
+            &[
+                "--algo",
+                "restic",
+                "--poly",
+                "3DA3358B4DC173",
+                "--max",
+                "1K",
+            ],
+            &["--algo", "restic", "--poly", "8000000000000001"],
+            &["--algo", "restic", "--poly", "7"],
+            &["--algo", "normalized-gear", "--bits", "2"],
+            &["--algo", "zpaq-compatible", "--fragment", "23"],
+            &["--algo", "rabin"],
+            &["--algo", "adler", "--window", "0"],
+            &["--algo", "buzhash", "--min", "1000"],
+            &["--algo", "buzhash", "--min", "4K", "--max", "4K"],
+            &["--algo", "buzhash", "--table", "/nonexistent"],
+            &["--algo", "casync"],
+            &["--algo", "tttd", "--poly", "3DA3358B4DC173", "--avg", "0"],
+            &["--algo", "tttd", "--poly", "3DA3358B4DC173", "--max", "100"],
+        ] {
+            assert!(make_chunker(&args(list)).is_err(), "{:?}", list);
+        }
+        assert!(make_chunker(&args(&["--avg", "8"])).is_ok());
+        assert!(make_chunker(&args(&["--algo", "restic", "--poly", "3DA3358B4DC173"])).is_ok());
+    }
+
+    #[test]
+    fn test_table() {
+        let path = env::temp_dir().join(format!("cdchunk-table-{}", process::id()));
+        let path = path.to_str().unwrap();
+        let table: Vec<_> = (0..256u32)
+            .map(|i| format!("0x{:08x}", i.wrapping_mul(0x9e37_79b9)))
+            .collect();
+        fs::write(path, table.join(",\n")).unwrap();
+        assert_eq!(read_table(path).unwrap()[1], 0x9e37_79b9);
+
+        let data: Vec<u8> = (0..100_000u64).map(|i| (i * i % 251) as u8).collect();
+        for list in &[
+            &["--algo", "casync", "--table", path, "--avg", "1K"][..],
+            &[
+                "--algo", "buzhash", "--table", path, "--min", "1K", "--max", "8K",
+            ],
+        ] {
+            let chunker = make_chunker(&args(list)).unwrap();
+            let total: usize = Chunker::new(chunker).slices(&data).map(|c| c.len()).sum();
+            assert_eq!(total, data.len());
+        }
+
+        fs::write(path, table[..255].join(" ")).unwrap();
+        assert!(read_table(path).is_err());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_print_chunk() {
+        let data = [1u8; 10];
+        let chunker = make_chunker(&args(&["--algo", "fixed", "--size", "6"])).unwrap();
+        let mut out = Vec::new();
+        for (i, info) in Chunker::new(chunker).chunks(&data[..]).enumerate() {
+            let info = info.unwrap();
+            print_chunk(&mut out, "a", true, &info, &[], Format::Text, i == 0).unwrap();
+            print_chunk(&mut out, "a", false, &info, &[0xab], Format::Csv, i == 0).unwrap();
+            print_chunk(&mut out, "a", false, &info, &[], Format::Json, i == 0).unwrap();
+        }
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "a 0 6\n\"a\",0,6,ab\n\n  {\"file\": \"a\", \"offset\": 0, \"length\": 6}\
+             a 6 4\n\"a\",6,4,ab\n,\n  {\"file\": \"a\", \"offset\": 6, \"length\": 4}"
+        );
+    }
+
+    #[test]
+    fn test_print_analysis() {
+        let data = vec![7u8; 1000];
+        let mut analysis = DedupAnalysis::new();
+        for _ in 0..2 {
+            let chunker = make_chunker(&args(&["--algo", "fixed", "--size", "100"])).unwrap();
+            let hasher = Hasher::new(Some("sha256")).unwrap();
+            analysis
+                .add_reader(Chunker::new(chunker), &data[..], hasher)
+                .unwrap();
+        }
+        let mut out = Vec::new();
+        print_analysis(&analysis, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("unique bytes:  100\n"));
+        assert!(out.contains("unique chunks: 1\n"));
+        assert!(out.contains("dedup ratio:   20.000\n"));
+        assert!(out.contains("chunk size:    min 100, max 100\n"));
+        assert!(out.contains("        64 - 127        20\n"));
+    }
+}
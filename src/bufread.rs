@@ -0,0 +1,130 @@
+use std::io::{self, BufRead};
+
+use {ChunkInput, ChunkerImpl, EmitStatus, Lookahead};
+
+/// Streams chunks from the buffer of a `BufRead` object, without copying.
+///
+/// This is created by `Chunker::stream_bufread()`. It works like `ChunkStream`,
+/// but the `Data` items are slices of the reader's own buffer, obtained from
+/// `fill_buf()`. The bytes returned are consumed on the next call to `read()`,
+/// or by `into_inner()`.
+///
+/// If the chunking method has a `ChunkerImpl::lookahead()`, the data is copied
+/// to a separate buffer instead, as it is needed before being returned.
+pub struct BufReadChunkStream<R: BufRead, I: ChunkerImpl> {
+    reader: R,
+    inner: I,
+    consume: usize, // How much data to consume from the reader before the next read
+    status: EmitStatus,
+    lookahead: Option<Lookahead>,
+}
+
+impl<R: BufRead, I: ChunkerImpl> BufReadChunkStream<R, I> {
+    pub(crate) fn new(reader: R, inner: I) -> BufReadChunkStream<R, I> {
+        let lookahead = inner.lookahead().map(Lookahead::new);
+        BufReadChunkStream {
+            reader,
+            inner,
+            consume: 0,
+            status: EmitStatus::Data,
+            lookahead,
+        }
+    }
+
+    /// Iterate on the chunks, returning `ChunkInput` items.
+    ///
+    /// This is the same as `ChunkStream::read()`.
+    pub fn read(&mut self) -> Option<io::Result<ChunkInput<'_>>> {
+        self.reader.consume(self.consume);
+        self.consume = 0;
+
+        if let Some(ref mut lookahead) = self.lookahead {
+            while lookahead.needs_data() {
+                let len = match self.reader.fill_buf() {
+                    Ok(buffer) => {
+                        lookahead.push(buffer);
+                        buffer.len()
+                    }
+                    Err(e) => return Some(Err(e)),
+                };
+                self.reader.consume(len);
+            }
+            return lookahead.step(&mut self.inner, &mut self.status).map(Ok);
+        }
+
+        if self.status == EmitStatus::AtSplit {
+            self.status = EmitStatus::End;
+            self.inner.reset();
+            return Some(Ok(ChunkInput::End));
+        }
+        let buffer = match self.reader.fill_buf() {
+            Ok(b) => b,
+            Err(e) => return Some(Err(e)),
+        };
+        if buffer.is_empty() {
+            if self.status == EmitStatus::Data {
+                self.status = EmitStatus::End;
+                return Some(Ok(ChunkInput::End));
+            }
+            return None;
+        }
+        if let Some(split) = self.inner.find_boundary(buffer) {
+            assert!(split < buffer.len());
+            self.status = EmitStatus::AtSplit;
+            self.consume = split + 1;
+            return Some(Ok(ChunkInput::Data(&buffer[..split + 1])));
+        }
+        self.status = EmitStatus::Data;
+        self.consume = buffer.len();
+        Some(Ok(ChunkInput::Data(buffer)))
+    }
+
+    /// Returns the reader, positioned after the data returned so far.
+    ///
+    /// With a lookahead, the reader is further, after the data that was copied.
+    pub fn into_inner(mut self) -> R {
+        self.reader.consume(self.consume);
+        self.reader
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{self, Rng};
+    use std::io::{BufRead, BufReader, Read};
+
+    use {ChunkInput, Chunker, GearChunker};
+
+    #[test]
+    fn test_bufread() {
+        let mut data = vec![0u8; 1 << 14];
+        rand::thread_rng().fill(&mut data[..]);
+        let chunker = || Chunker::new(GearChunker::new(0xfc00_0000));
+        let expected: Vec<_> = chunker().slices(&data).collect();
+
+        let mut stream = chunker().stream_bufread(BufReader::with_capacity(100, &data[..]));
+        let mut result = vec![Vec::new()];
+        while let Some(input) = stream.read() {
+            match input.unwrap() {
+                ChunkInput::Data(d) => result.last_mut().unwrap().extend_from_slice(d),
+                ChunkInput::End => result.push(Vec::new()),
+            }
+        }
+        assert_eq!(result.pop(), Some(Vec::new()));
+        assert_eq!(result, expected);
+
+        // Slices borrow the reader's data
+        let mut stream = chunker().stream_bufread(&data[..]);
+        match stream.read() {
+            Some(Ok(ChunkInput::Data(d))) => assert_eq!(d.as_ptr(), data.as_ptr()),
+            _ => panic!(),
+        }
+
+        // The reader is left after the returned data
+        let mut reader = stream.into_inner();
+        assert_eq!(reader.fill_buf().unwrap(), &data[expected[0].len()..]);
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest.len(), data.len() - expected[0].len());
+    }
+}
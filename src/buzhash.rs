@@ -0,0 +1,274 @@
+use std::cmp::min;
+use std::mem::size_of;
+
+use gear::TABLE;
+use ChunkerImpl;
+
+/// A chunker using Buzhash, a cyclic polynomial rolling hash, following borgbackup's chunker.
+///
+/// The hash of a window of `window_size` bytes is computed by rotating and XORing values from a
+/// 256-entry table, which is XORed with a `seed`. Like borg, the windows that are checked start
+/// at the cut-point: a chunk is cut *before* the first window whose lowest `mask_bits` bits are
+/// zero, starting with the window at `2^min_exp` bytes. A window is only checked if at least one
+/// byte follows it, so the last `window_size` bytes of the data are never a cut-point. Chunks are
+/// at most `2^max_exp` bytes long, and the bytes before the first window are not hashed.
+///
+/// Since a cut-point is only known once the window after it was read, `lookahead()` is
+/// `2^max_exp`: the streams keep up to that many bytes in memory. If the data is given to
+/// `find_boundary()` in smaller parts anyway, a cut-point in a previous part can't be used, and
+/// the chunk is cut where it was found instead.
+///
+/// The parameters are borg's `--chunker-params buzhash,MIN_EXP,MAX_EXP,MASK_BITS,WINDOW_SIZE`,
+/// borg's default being `19,23,21,4095`. For the boundaries to match borg's, the table has to be
+/// borg's `table_base` (from `src/borg/_chunker.c`), given to `with_table()` with the seed of the
+/// repository. That table isn't included in this crate, and the boundaries haven't been checked
+/// against chunk lists produced by borg.
+///
+/// Source: Jonathan D. Cohen. "Recursive hashing functions for n-grams." ACM Transactions on
+/// Information Systems 15.3 (1997): 291-320.
+/// https://doi.org/10.1145/256163.256168
+#[derive(Debug, Clone)]
+pub struct BuzhashChunker {
+    table: [u32; 256],
+    mask: u32,
+    min_size: usize,
+    max_size: usize,
+    state: BuzhashState,
+}
+
+impl BuzhashChunker {
+    /// Creates a new Buzhash chunker.
+    ///
+    /// Note that XORing the table with the seed only XORs the hash with a constant, which is zero
+    /// if `window_size` is a multiple of 64, in which case the seed has no effect.
+    pub fn new(
+        window_size: usize,
+        seed: u32,
+        mask_bits: u32,
+        min_exp: u32,
+        max_exp: u32,
+    ) -> BuzhashChunker {
+        BuzhashChunker::with_table(TABLE, window_size, seed, mask_bits, min_exp, max_exp)
+    }
+
+    /// Creates a new Buzhash chunker using the given table instead of the default one.
+    pub fn with_table(
+        table: [u32; 256],
+        window_size: usize,
+        seed: u32,
+        mask_bits: u32,
+        min_exp: u32,
+        max_exp: u32,
+    ) -> BuzhashChunker {
+        assert!(mask_bits < 32, "mask_bits needs to be less than 32");
+        assert!(window_size > 0, "window_size needs to be at least 1");
+        assert!(
+            (max_exp as usize) < 8 * size_of::<usize>(),
+            "max_exp needs to be less than the bits of usize"
+        );
+        assert!(
+            (1 << min_exp) + window_size < 1 << max_exp,
+            "the maximum size needs to be more than the minimum size plus window_size"
+        );
+        let mut table = table;
+        for v in table.iter_mut() {
+            *v ^= seed;
+        }
+        BuzhashChunker {
+            table,
+            mask: (1 << mask_bits) - 1,
+            min_size: 1 << min_exp,
+            max_size: 1 << max_exp,
+            state: BuzhashState::new(window_size),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct BuzhashState {
+    pub(crate) hash: u32,
+    pub(crate) window: Vec<u8>,
+    wpos: usize,
+    pub(crate) pos: usize,
+}
+
+impl BuzhashState {
+    pub(crate) fn new(window_size: usize) -> BuzhashState {
+        BuzhashState {
+            hash: 0,
+            window: vec![0; window_size],
+            wpos: 0,
+            pos: 0,
+        }
+    }
+
+    /// Adds a byte to the hash, removing the byte that falls out of the window once it is full.
+    pub(crate) fn ingest(&mut self, b: u8, table: &[u32; 256], window_full: bool) {
+        self.hash = self.hash.rotate_left(1) ^ table[b as usize];
+        if window_full {
+            let out = self.window[self.wpos];
+            self.hash ^= table[out as usize].rotate_left(self.window.len() as u32);
+        }
+        self.window[self.wpos] = b;
+        self.wpos += 1;
+        if self.wpos == self.window.len() {
+            self.wpos = 0;
+        }
+    }
+
+    pub(crate) fn reset(&mut self) {
+        self.hash = 0;
+        self.wpos = 0;
+        self.pos = 0;
+    }
+}
+
+impl ChunkerImpl for BuzhashChunker {
+    fn find_boundary(&mut self, data: &[u8]) -> Option<usize> {
+        let window_size = self.state.window.len();
+        let start_pos = self.state.pos;
+
+        // Skip the bytes before the first window
+        let skip = self.min_size.saturating_sub(start_pos);
+        let start = min(skip, data.len());
+        self.state.pos += start;
+
+        for (i, &b) in data.iter().enumerate().skip(start) {
+            let window_full = self.state.pos >= self.min_size + window_size;
+
+            // Now that a byte follows the window, check it
+            if window_full && self.state.hash & self.mask == 0 {
+                let cut = self.state.pos - window_size;
+                return if cut > start_pos {
+                    Some(cut - start_pos - 1)
+                } else {
+                    Some(i)
+                };
+            }
+
+            self.state.ingest(b, &self.table, window_full);
+            self.state.pos += 1;
+            if self.state.pos >= self.max_size {
+                return Some(i);
+            }
+        }
+
+        // No cut-point found within this block of data.
+        None
+    }
+
+    fn reset(&mut self) {
+        self.state.reset();
+    }
+
+    fn lookahead(&self) -> Option<usize> {
+        Some(self.max_size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use std::cmp::min;
+
+    use super::BuzhashChunker;
+    use Chunker;
+
+    /// Computes the hash of a whole window from scratch.
+    fn buzhash(data: &[u8], table: &[u32; 256]) -> u32 {
+        let len = data.len();
+        data.iter().enumerate().fold(0, |sum, (i, &b)| {
+            sum ^ table[b as usize].rotate_left((len - 1 - i) as u32)
+        })
+    }
+
+    #[test]
+    fn test_rolling() {
+        let mut data = [0u8; 300];
+        StdRng::seed_from_u64(5).fill(&mut data[..]);
+
+        // Use a minimum size equal to the window so that all bytes are hashed
+        let mut chunker = BuzhashChunker::new(64, 0x1234_5678, 31, 6, 16);
+        for (i, &b) in data.iter().enumerate() {
+            let window_full = chunker.state.pos >= 64;
+            let table = chunker.table;
+            chunker.state.ingest(b, &table, window_full);
+            chunker.state.pos += 1;
+            if i >= 63 {
+                assert_eq!(chunker.state.hash, buzhash(&data[i - 63..i + 1], &table));
+            }
+        }
+    }
+
+    /// Chunks like borg's `chunker_process()`, which looks for the cut-point
+    /// in a buffer of at most the maximum size.
+    fn borg_chunks(data: &[u8], chunker: &BuzhashChunker) -> Vec<usize> {
+        let window_size = chunker.state.window.len();
+        let mut sizes = Vec::new();
+        let mut last = 0;
+        while last < data.len() {
+            let end = min(data.len(), last + chunker.max_size);
+            let mut position = last + chunker.min_size;
+            let mut size = end - last;
+            while position + window_size < end {
+                let window = &data[position..position + window_size];
+                if buzhash(window, &chunker.table) & chunker.mask == 0 {
+                    size = position - last;
+                    break;
+                }
+                position += 1;
+            }
+            sizes.push(size);
+            last += size;
+        }
+        sizes
+    }
+
+    #[test]
+    fn test_chunks() {
+        let mut data = vec![0u8; 1 << 18];
+        StdRng::seed_from_u64(6).fill(&mut data[..]);
+        let chunker = |seed| BuzhashChunker::new(63, seed, 10, 8, 12);
+
+        let expected = borg_chunks(&data, &chunker(0));
+        assert!(expected.iter().any(|&l| l < 1 << 12));
+        assert!(expected.contains(&(1 << 12)));
+        let result: Vec<_> = Chunker::new(chunker(0))
+            .slices(&data)
+            .map(|c| c.len())
+            .collect();
+        assert_eq!(result, expected);
+
+        let result: Vec<_> = Chunker::new(chunker(0))
+            .chunks(&data[..])
+            .map(|c| c.unwrap().length())
+            .collect();
+        assert_eq!(result, expected);
+
+        // The seed changes the boundaries
+        let result: Vec<_> = Chunker::new(chunker(0xdead_beef))
+            .slices(&data)
+            .map(|c| c.len())
+            .collect();
+        assert!(result != expected);
+        assert_eq!(result, borg_chunks(&data, &chunker(0xdead_beef)));
+    }
+
+    #[test]
+    fn test_end() {
+        // The last window_size bytes are never a cut-point, and the rest
+        // is a single chunk if it is shorter than the minimum plus a window
+        let data = [0u8; 2000];
+        let sizes = |len| -> Vec<usize> {
+            Chunker::new(BuzhashChunker::new(16, 0, 0, 8, 12))
+                .slices(&data[..len])
+                .map(|c| c.len())
+                .collect()
+        };
+        assert_eq!(sizes(256 + 16), vec![256 + 16]);
+        assert_eq!(sizes(256 + 17), vec![256, 17]);
+        assert_eq!(sizes(600), vec![256, 256, 88]);
+    }
+}
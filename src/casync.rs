@@ -0,0 +1,156 @@
+use std::cmp::{max, min};
+
+use buzhash::BuzhashState;
+use ChunkerImpl;
+
+/// Window size used by casync.
+const CASYNC_WINDOW_SIZE: usize = 48;
+
+/// A chunker following the chunking rules of casync and desync.
+///
+/// This uses Buzhash over a 48-byte window, and sets a boundary when `hash % discriminator ==
+/// discriminator - 1`, where the discriminator is derived from the average size using casync's
+/// formula. The hash is reset after each chunk, and no boundary is set before the window is full,
+/// except at the maximum size.
+///
+/// The table has to be casync's `buzhash_table` (found in `src/buzhash.c`, and as `hashTable` in
+/// desync) for the boundaries to match casync's; it isn't included in this crate, and the
+/// boundaries haven't been checked against chunk lists produced by casync. casync's default sizes
+/// are 16 KiB, 64 KiB and 256 KiB.
+///
+/// Source: https://github.com/systemd/casync/blob/main/src/cachunker.c
+#[derive(Debug, Clone)]
+pub struct CasyncChunker {
+    table: [u32; 256],
+    min_size: usize,
+    max_size: usize,
+    discriminator: u32,
+    state: BuzhashState,
+}
+
+impl CasyncChunker {
+    /// Creates a new casync chunker, `table` being casync's Buzhash table.
+    pub fn new(
+        table: [u32; 256],
+        min_size: usize,
+        avg_size: usize,
+        max_size: usize,
+    ) -> CasyncChunker {
+        assert!(min_size > 0, "min_size needs to be at least 1");
+        assert!(
+            min_size <= avg_size && avg_size <= max_size,
+            "sizes need to be min_size <= avg_size <= max_size"
+        );
+        CasyncChunker {
+            table,
+            min_size,
+            max_size,
+            discriminator: discriminator(avg_size),
+            state: BuzhashState::new(CASYNC_WINDOW_SIZE),
+        }
+    }
+}
+
+/// Computes the discriminator for an average size, like casync's
+/// `CA_CHUNKER_DISCRIMINATOR_FROM_AVG()`.
+///
+/// It is at least 1, which casync's formula doesn't give for tiny sizes.
+fn discriminator(avg_size: usize) -> u32 {
+    let avg_size = avg_size as f64;
+    let discriminator = (avg_size / (-1.428_888_52e-7 * avg_size + 1.332_375_15)) as u32;
+    discriminator.max(1)
+}
+
+impl ChunkerImpl for CasyncChunker {
+    fn find_boundary(&mut self, data: &[u8]) -> Option<usize> {
+        // Skip the bytes that can't influence the first hash we check
+        let skip = self
+            .min_size
+            .saturating_sub(CASYNC_WINDOW_SIZE)
+            .saturating_sub(self.state.pos);
+        let start = min(skip, data.len());
+        self.state.pos += start;
+
+        // The window is full once we hashed CASYNC_WINDOW_SIZE bytes
+        let full_pos = max(self.min_size, CASYNC_WINDOW_SIZE);
+
+        for (i, &b) in data.iter().enumerate().skip(start) {
+            let window_full = self.state.pos >= full_pos;
+            self.state.ingest(b, &self.table, window_full);
+            self.state.pos += 1;
+
+            if self.state.pos >= self.max_size
+                || (self.state.pos >= full_pos
+                    && self.state.hash % self.discriminator == self.discriminator - 1)
+            {
+                return Some(i);
+            }
+        }
+
+        // No cut-point found within this block of data.
+        None
+    }
+
+    fn reset(&mut self) {
+        self.state.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::{discriminator, CasyncChunker};
+    use gear::TABLE;
+    use Chunker;
+
+    #[test]
+    fn test_discriminator() {
+        // Values of CA_CHUNKER_DISCRIMINATOR_FROM_AVG()
+        assert_eq!(discriminator(64 * 1024), 49535);
+        assert_eq!(discriminator(16 * 1024), 12318);
+        assert_eq!(discriminator(1), 1);
+    }
+
+    #[test]
+    fn test_chunks() {
+        let mut data = vec![0u8; 1 << 20];
+        StdRng::seed_from_u64(11).fill(&mut data[..]);
+        // Not casync's table, but gives the same size distribution
+        let chunker = || CasyncChunker::new(TABLE, 1024, 4096, 16384);
+
+        let expected: Vec<_> = Chunker::new(chunker())
+            .slices(&data)
+            .map(|c| c.len())
+            .collect();
+        let (_, rest) = expected.split_last().unwrap();
+        assert!(rest.iter().all(|&l| (1024..=16384).contains(&l)));
+        let average = data.len() / expected.len();
+        assert!(3500 < average && average < 4700);
+
+        let result: Vec<_> = Chunker::new(chunker())
+            .chunks(&data[..])
+            .map(|c| c.unwrap().length())
+            .collect();
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_small_sizes() {
+        // Chunks are cut at the maximum size even before the window is full
+        let data = [0u8; 100];
+        let sizes: Vec<_> = Chunker::new(CasyncChunker::new(TABLE, 1, 1, 16))
+            .slices(&data)
+            .map(|c| c.len())
+            .collect();
+        assert_eq!(sizes, vec![16, 16, 16, 16, 16, 16, 4]);
+
+        // With an average size of 1, a boundary is set as soon as the window is full
+        let sizes: Vec<_> = Chunker::new(CasyncChunker::new(TABLE, 1, 1, 64))
+            .slices(&data)
+            .map(|c| c.len())
+            .collect();
+        assert_eq!(sizes, vec![48, 48, 4]);
+    }
+}
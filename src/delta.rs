@@ -0,0 +1,329 @@
+//! Synchronization of files using content-defined chunks, like rsync.
+//!
+//! The receiver, who has an old version of a file, computes its `signature()`,
+//! the list of its chunks and their digests. The sender, who has the new
+//! version, uses it to compute a `delta()`: the chunks that the receiver already
+//! has are replaced by references to the old file, and only the other chunks
+//! are sent as literal data. The receiver then uses `patch()` to rebuild the new
+//! version of the file from the old version and the delta.
+//!
+//! Both sides have to use the same chunking method and hash function. Unlike
+//! rsync, which finds blocks of the old file at any offset in the new file,
+//! this only finds the chunks that are cut identically in both files, which
+//! content-defined chunking makes likely.
+
+use std::collections::HashMap;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use varint::{read_varint, write_varint};
+use {ChunkHasher, Chunker, ChunkerImpl};
+
+/// Magic bytes at the start of the encoding of a delta.
+const DELTA_MAGIC: &[u8; 4] = b"CDCD";
+
+/// Current version of the encoding.
+const VERSION: u8 = 1;
+
+const OP_COPY: u8 = 0;
+const OP_LITERAL: u8 = 1;
+
+/// A chunk in a `Signature`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignatureEntry {
+    offset: u64,
+    length: u64,
+    digest: Vec<u8>,
+}
+
+impl SignatureEntry {
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn length(&self) -> u64 {
+        self.length
+    }
+
+    pub fn digest(&self) -> &[u8] {
+        &self.digest
+    }
+}
+
+/// The chunks of a file, with their position and digest.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Signature {
+    entries: Vec<SignatureEntry>,
+}
+
+impl Signature {
+    pub fn entries(&self) -> &[SignatureEntry] {
+        &self.entries
+    }
+}
+
+/// Computes the signature of a file.
+pub fn signature<I, R, H>(chunker: Chunker<I>, old: R, hasher: H) -> io::Result<Signature>
+where
+    I: ChunkerImpl,
+    R: Read,
+    H: ChunkHasher,
+{
+    let mut entries = Vec::new();
+    for chunk in chunker.hashed_chunks(old, hasher) {
+        let (info, digest) = chunk?;
+        entries.push(SignatureEntry {
+            offset: info.start() as u64,
+            length: info.length() as u64,
+            digest: digest.as_ref().to_owned(),
+        });
+    }
+    Ok(Signature { entries })
+}
+
+/// An instruction in a `Delta`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeltaOp {
+    /// Copy bytes from the old file.
+    Copy { offset: u64, length: u64 },
+    /// Data that is not in the old file.
+    Literal(Vec<u8>),
+}
+
+/// The instructions to build a new file from an old one.
+///
+/// The encoding is the magic `CDCD`, the version byte, the number of
+/// instructions, then each instruction: a 0 byte followed by the offset and
+/// length for `Copy`, a 1 byte followed by the length and the data for
+/// `Literal`. Numbers are encoded as LEB128 variable-length integers.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Delta {
+    ops: Vec<DeltaOp>,
+}
+
+impl Delta {
+    pub fn ops(&self) -> &[DeltaOp] {
+        &self.ops
+    }
+
+    /// The size of the literal data, that the old file didn't have.
+    pub fn literal_length(&self) -> u64 {
+        self.ops
+            .iter()
+            .map(|op| match *op {
+                DeltaOp::Copy { .. } => 0,
+                DeltaOp::Literal(ref data) => data.len() as u64,
+            })
+            .sum()
+    }
+
+    /// Adds a copy, merging it with the previous one if they are contiguous.
+    fn push_copy(&mut self, offset: u64, length: u64) {
+        if let Some(&mut DeltaOp::Copy {
+            offset: last_offset,
+            length: ref mut last_length,
+        }) = self.ops.last_mut()
+        {
+            if last_offset + *last_length == offset {
+                *last_length += length;
+                return;
+            }
+        }
+        self.ops.push(DeltaOp::Copy { offset, length });
+    }
+
+    /// Adds literal data, merging it with the previous literal.
+    fn push_literal(&mut self, data: Vec<u8>) {
+        if let Some(&mut DeltaOp::Literal(ref mut last)) = self.ops.last_mut() {
+            last.extend_from_slice(&data);
+            return;
+        }
+        self.ops.push(DeltaOp::Literal(data));
+    }
+
+    /// Writes the encoding of the delta.
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(DELTA_MAGIC)?;
+        writer.write_all(&[VERSION])?;
+        write_varint(&mut writer, self.ops.len() as u64)?;
+        for op in &self.ops {
+            match *op {
+                DeltaOp::Copy { offset, length } => {
+                    writer.write_all(&[OP_COPY])?;
+                    write_varint(&mut writer, offset)?;
+                    write_varint(&mut writer, length)?;
+                }
+                DeltaOp::Literal(ref data) => {
+                    writer.write_all(&[OP_LITERAL])?;
+                    write_varint(&mut writer, data.len() as u64)?;
+                    writer.write_all(data)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads a delta from its encoding.
+    pub fn read<R: Read>(mut reader: R) -> io::Result<Delta> {
+        let invalid_data = |message| io::Error::new(io::ErrorKind::InvalidData, message);
+        let mut header = [0u8; 5];
+        reader.read_exact(&mut header)?;
+        if &header[..4] != DELTA_MAGIC {
+            return Err(invalid_data("not a delta"));
+        }
+        if header[4] != VERSION {
+            return Err(invalid_data("unsupported delta version"));
+        }
+        let count = read_varint(&mut reader)?;
+        let mut ops = Vec::new();
+        for _ in 0..count {
+            let mut tag = [0u8];
+            reader.read_exact(&mut tag)?;
+            match tag[0] {
+                OP_COPY => {
+                    let offset = read_varint(&mut reader)?;
+                    let length = read_varint(&mut reader)?;
+                    ops.push(DeltaOp::Copy { offset, length });
+                }
+                OP_LITERAL => {
+                    let length = read_varint(&mut reader)?;
+                    let mut data = Vec::new();
+                    (&mut reader).take(length).read_to_end(&mut data)?;
+                    if data.len() as u64 != length {
+                        return Err(io::ErrorKind::UnexpectedEof.into());
+                    }
+                    ops.push(DeltaOp::Literal(data));
+                }
+                _ => return Err(invalid_data("invalid delta instruction")),
+            }
+        }
+        Ok(Delta { ops })
+    }
+}
+
+/// Computes the delta between a new file and an old one, given its signature.
+///
+/// `chunker` and `hasher` have to work like the ones used for the signature.
+pub fn delta<I, R, H>(
+    signature: &Signature,
+    chunker: Chunker<I>,
+    new: R,
+    mut hasher: H,
+) -> io::Result<Delta>
+where
+    I: ChunkerImpl,
+    R: Read,
+    H: ChunkHasher,
+{
+    let mut old_chunks = HashMap::new();
+    for entry in &signature.entries {
+        old_chunks
+            .entry((&entry.digest[..], entry.length))
+            .or_insert(entry.offset);
+    }
+
+    let mut delta = Delta::default();
+    for chunk in chunker.whole_chunks(new) {
+        let chunk = chunk?;
+        let digest = hasher.digest(&chunk);
+        let length = chunk.len() as u64;
+        match old_chunks.get(&(digest.as_ref(), length)) {
+            Some(&offset) => delta.push_copy(offset, length),
+            None => delta.push_literal(chunk),
+        }
+    }
+    Ok(delta)
+}
+
+/// Writes the new file, from the old file and the delta.
+///
+/// Returns the number of bytes written.
+pub fn patch<R, W>(mut old: R, delta: &Delta, mut writer: W) -> io::Result<u64>
+where
+    R: Read + Seek,
+    W: Write,
+{
+    let mut total = 0;
+    for op in &delta.ops {
+        match *op {
+            DeltaOp::Copy { offset, length } => {
+                old.seek(SeekFrom::Start(offset))?;
+                let copied = io::copy(&mut (&mut old).take(length), &mut writer)?;
+                if copied != length {
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+                total += length;
+            }
+            DeltaOp::Literal(ref data) => {
+                writer.write_all(data)?;
+                total += data.len() as u64;
+            }
+        }
+    }
+    Ok(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::io::Cursor;
+
+    use super::{delta, patch, signature, Delta, DeltaOp};
+    use hash::tests::Fnv;
+    use {Chunker, FastCDC};
+
+    fn round_trip(old: &[u8], new: &[u8]) -> Delta {
+        let chunker = || Chunker::new(FastCDC::new(256, 1024, 4096));
+        let signature = signature(chunker(), old, Fnv::new()).unwrap();
+        let delta = delta(&signature, chunker(), new, Fnv::new()).unwrap();
+
+        let mut encoded = Vec::new();
+        delta.write(&mut encoded).unwrap();
+        let decoded = Delta::read(&encoded[..]).unwrap();
+        assert_eq!(decoded, delta);
+        assert!(Delta::read(&encoded[..encoded.len() - 1]).is_err());
+
+        let mut result = Vec::new();
+        let written = patch(Cursor::new(old), &decoded, &mut result).unwrap();
+        assert_eq!(written, new.len() as u64);
+        assert_eq!(result, new);
+        delta
+    }
+
+    #[test]
+    fn test_delta() {
+        let mut rng = StdRng::seed_from_u64(16);
+        let mut old = vec![0u8; 1 << 16];
+        rng.fill(&mut old[..]);
+
+        // Insert, remove and modify some data
+        let mut new = old.clone();
+        new.splice(1000..1000, vec![1, 2, 3]);
+        new.drain(30000..31000);
+        new[50000] ^= 1;
+        let delta = round_trip(&old, &new);
+        assert!(delta.literal_length() < 16384);
+        match delta.ops()[0] {
+            DeltaOp::Literal(_) => {}
+            _ => panic!("first chunk should be modified"),
+        }
+
+        // Same file: one copy
+        let delta = round_trip(&old, &old);
+        assert_eq!(
+            delta.ops(),
+            &[DeltaOp::Copy {
+                offset: 0,
+                length: old.len() as u64,
+            }]
+        );
+
+        // Unrelated files, and empty files
+        let mut other = vec![0u8; 10000];
+        rng.fill(&mut other[..]);
+        let delta = round_trip(&old, &other);
+        assert_eq!(delta.ops(), &[DeltaOp::Literal(other.clone())]);
+        round_trip(&old, &[]);
+        round_trip(&[], &other);
+    }
+}
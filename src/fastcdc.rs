@@ -0,0 +1,429 @@
+use ChunkerImpl;
+
+/// Array of 256 random 64-bit values.
+/// Created using python as such:
+///
+/// ```python
+/// import random
+///
+/// random.seed(0x46617374434443)
+/// values = [random.getrandbits(64) for _ in range(256)]
+///
+/// for value in values:
+///     print("0x%016x" % value)
+/// ```
+#[rustfmt::skip]
+pub static TABLE64: [u64; 256] = [
+    0xf180659c6f27bb36, 0x872a4bf64c3dccaa, 0x97da01d9f6981ad0, 0x42066bf78139a2e1,
+    0x16ef945e813a2b24, 0x57cea1910b81cccb, 0xb99b32499c4d3f0c, 0x0e82b85899de539a,
+    0xc81e8aecfb31aa2a, 0x71625a3bf2bf7778, 0xa9e951e949e63276, 0xf4122744f1f053cf,
+    0x93aa297915415aaf, 0xcfefd43098ea6219, 0xe766ca13d5698aa4, 0xb3f0443917286fd1,
+    0x6b0e9109e53d7b05, 0x482cc78a72ac33f2, 0x192643271e1387dc, 0xd50220168cacfe9b,
+    0x8530b8282f4ef107, 0x9d5705eb9e1b2b9f, 0x7079f6c72dd7f2c0, 0xed03cf7d326196ff,
+    0xdf5c28276582432a, 0xf1791e2c000d2cff, 0x812edcc19dcf80b3, 0xe8f12718bd1e534b,
+    0x3cc4e04efb5c111e, 0xb720f1b5e641416a, 0x1134c8263b28be0b, 0x2e95a448ff865b77,
+    0x3302731e8778111e, 0xe15f3e1e2c49849b, 0x7b7b72b4c697e4a0, 0xeaebb7f2c7a3b92d,
+    0x01f46fcb70cceeac, 0x1bdd1f21f65bba59, 0xef4ffb95519d02fb, 0x1a36045ef8e04021,
+    0x95650930fdeef85d, 0xf37a857e713b5770, 0xccb31211f31e7f22, 0x742e782157d83d95,
+    0x3b944775957a9345, 0xd4a1406d2c609a7c, 0xcfa55a5ca2e7a952, 0x0fb6f078916d9dc0,
+    0x56eeb2779bd0542e, 0x5b5306de76602b45, 0x840170bb712f7d2e, 0x6ad66643b4dd9926,
+    0x390c1d3b545cc897, 0x6d751a1553a82097, 0x6f3e9a33ae7a12ef, 0x0cdfab83031eefe3,
+    0xc2492b671d446c5c, 0x996becdae3d9ab07, 0x38713c8608ea5dca, 0x6b243487c987a2a5,
+    0xd560a0d25589dacd, 0xac3130d565d5f6b3, 0x3570b1bd42db673e, 0xd833cdddbcbc27bc,
+    0xec115185f9fbad42, 0xdf44d4aa9d2b3560, 0xc49845293c1a1808, 0x66ac41fc15d36b53,
+    0xc8f618e43fb983de, 0xd11457ff697e6b2b, 0xc2da9a940d640497, 0x1d282e55f7be1782,
+    0xca7f716bd8bd9938, 0x5002066da32ee533, 0x78695a4f0a1ad95d, 0x66dbbfe0a3b3b0fc,
+    0x09c13129a6075a71, 0x339220eedde26321, 0x0e2811138da5e2fc, 0xc92e011d9aa40958,
+    0x2e768a8c067d75a8, 0x4282f43f04e2fb48, 0xc270573b6d939128, 0x9d26a5abc3d43556,
+    0x59d8506c3284d16e, 0x91681d77b197ef10, 0x343ebf2b4ea21c4a, 0x32b5ab5ba758f108,
+    0x27072feb7a827b79, 0x5606543fdf58ad5c, 0xf0da53978f84f324, 0x452c144b5e018222,
+    0x5fd28d71bbba739c, 0x787e0f62a82a7a7c, 0xf3f472f32277c7ff, 0xc28ca83deae86d75,
+    0xf539c82fac5b1c32, 0x002327923da098a5, 0x6ce9e56112f89190, 0xa46818b7fc38c24b,
+    0xc73be1835eb25d15, 0x2c2050e82c0a407e, 0x0b78b97798601b9b, 0xcdd60c0c07c3e98f,
+    0x743ba4d57a70c79f, 0xe0d236b4b7584f2b, 0x54b5dca5eb11c01b, 0x995a0247a072c034,
+    0xe8f51fc43e75a08d, 0x989dbc6c7ea93c08, 0x9bd2746a10e94891, 0x2efacbbb047b3337,
+    0x127cbbd0495d75d7, 0x2ba27e165af8e9fa, 0x4eb8d4f851d88544, 0xeaca80284930736b,
+    0x594754813a31b9b2, 0x2ebd961e521caf11, 0xa1182c4b5eb4b552, 0x2db2cfe5dc2d9cde,
+    0x385ffb34fef8897f, 0xfd5cc5885c2438c1, 0xeaeaa7b4563d0b26, 0x749b92c8a3d8acf3,
+    0x27c8f125da825f08, 0x49ca26d0fb0bad28, 0x430534a0a888bdfa, 0x242d9639573905be,
+    0x3609f7ed30055bd7, 0x3b0a0599f6cb38e2, 0x8f997749d25a2dc9, 0x03135e0fc55ce99e,
+    0x185a5d7cc9bde279, 0x699f99d85b05964c, 0x247cec551b5d4dd8, 0x3d4d78c41fec8a0b,
+    0x0e188228cc835119, 0x3375c1f235d46c1a, 0xad106a2903857cfe, 0x07982a7ac028e0db,
+    0x1e4c489416c10d56, 0xf4d03f6164a021db, 0xd613e32f1f5ed6f6, 0x818f655216f02b0b,
+    0x6e8408721515d02b, 0x39e06b0dbec97b7e, 0xf2af5474716893d4, 0x5334af4fbe192697,
+    0x1fe17a20d41c498d, 0x43b7e705f48a44f7, 0x4dde1627ab0fad3e, 0x6e2db6647bb64fc0,
+    0x1ec7c729a20e9c8d, 0x72e31746169acabd, 0x34939ca3f347f92b, 0xc63be8ccd70eb9b5,
+    0x5183da26fd723582, 0x8a5ebb11e73d8559, 0x443e2e29618e223f, 0x7bc78679ac4bf453,
+    0xc2a1e0fffcf74082, 0xcf60a0af0be5f4c1, 0x31a05a20c7cde645, 0x192d38650219f026,
+    0x63f051376a0de1d8, 0xa30091e9a340a046, 0x1c214db4b906131e, 0x4d3fb0c1635b8e77,
+    0x4796f5c0a5770069, 0x464a7f475c51d090, 0x10b8cfeaa991c29c, 0x8849cf8a15495bc3,
+    0xa9a01532b38e46d0, 0x421edd792ce71ee5, 0x07cb12abd79604f0, 0x5a673de4fae806c8,
+    0x75281924036caf83, 0x8202e3811111daf8, 0x506b5cb49a1520da, 0xb643d54b3b591f88,
+    0xef574b2dd01c27c7, 0x6baf834ab164f8d1, 0xcfd672cb7b0b2349, 0x61eca31f42e15806,
+    0x11acc5a5196eeadb, 0x7a428f44524c499d, 0x754362717a9b294f, 0xc73286a970074c07,
+    0xf952cdbec81dade1, 0x2f4955ef163695e1, 0x7b2e80f63bb8f251, 0x1636e4a3f7c2840f,
+    0x1d4c41e9a6202525, 0xcb2c960ee641a206, 0x723c9bc5cc3b7bee, 0x6078e299b2955ad7,
+    0x25225a397b46e801, 0x3e2b23e837f0f495, 0x01687043f54a650f, 0xbcbf9ce7dfff9462,
+    0xf959509dea9ee6a1, 0x8d3f36ea5b6c50a0, 0x4c8eccbbcc4323d1, 0xa35188f162c3163a,
+    0x1e65cf05bba55deb, 0xdd96257690858547, 0x69327938addd3f3a, 0x0df518d818b76a8d,
+    0x919f0b80f4ef5d5f, 0x5ea79505decce9bd, 0x895a836b0e9e83bb, 0xdb37ac3187b1061a,
+    0x920e49d807a6d1d1, 0x1353e1b0fbb7a930, 0xa503f12375dd0fb8, 0x2dce2de8006a0dba,
+    0x4c737cc792ee05aa, 0x6ecac051481d4f2c, 0x063ea16e61615f57, 0x8e5a0dc50048ae14,
+    0xb03dd5453ac6af7c, 0x3cd3f7e753a6e75d, 0x773e9cee164c028c, 0xad6b5f5c61bdd56d,
+    0xb8d093dde8baf010, 0x031d28b28457c30f, 0xfd586da770fe9606, 0x04df5526ea3bce8e,
+    0x6e1fc6cb56f5b44d, 0xdfcf39c5c8ebc3c2, 0xe904c589f28f6e05, 0x88404508d0254417,
+    0x96fcfdd2a2cfa50a, 0x1dce25c1edaf6d79, 0x3154e6cd2603f342, 0x00a88b0e9d181b6a,
+    0xbfe11a3e81d313d5, 0xbde38c0798c9bfd4, 0x3409a56c5ee4ac6a, 0x2e328b3c5a8012e9,
+    0x8183c60d3c2df9e3, 0x32cb46febb8d3c61, 0x9b9b46dffe89f7ac, 0xe2259c0ddf29cee7,
+    0xead183d20b560240, 0x617d32da53ff5b9b, 0x08e6f5c46413cd64, 0x2c8bde3d91090b4d,
+    0x7ba64feb02c6307d, 0x8f859330edccd57e, 0xbd1bfa932b8ebd84, 0x07699e2e2d2b92d3,
+    0xd3ec3cc41cc90cf6, 0x8358af7d113ad20b, 0x1d8fd40f94118248, 0x6d0d4f18b79bb015,
+];
+
+/// The number of high bits of the 64-bit fingerprint over which mask bits are spread.
+///
+/// Bit `k` of a Gear fingerprint only depends on the last `k + 1` bytes, so using the high bits
+/// gives the hash a larger effective window, as recommended by the paper.
+const MASK_SPREAD: u32 = 48;
+
+/// Builds a mask with `bits` 1-bits spread evenly over the high bits of the fingerprint.
+///
+/// The most significant bit is never used. This doesn't change the probability of a match, but
+/// allows checking the mask on a fingerprint that has been shifted left by one bit.
+fn spread_mask(bits: u32) -> u64 {
+    assert!(bits > 0 && bits <= MASK_SPREAD);
+    (0..bits).fold(0, |mask, i| mask | 1 << (62 - i * MASK_SPREAD / bits))
+}
+
+/// A chunker implementing the FastCDC algorithm.
+///
+/// This algorithm is based on the Gear hash, with a 64-bit fingerprint, and adds:
+/// - Cut-point skipping: the first `min_size` bytes of a chunk are not hashed at all, since they
+///   can't contain a boundary.
+/// - Normalized chunking: a mask with more 1-bits is used until the chunk reaches the average
+///   size, then a mask with less 1-bits. The normalization level is the number of bits added to
+///   (resp. removed from) the mask for the average size. Level 0 disables normalization, the
+///   paper recommends level 2 (or 3).
+/// - Chunks are cut at `max_size`. This is done directly by the algorithm, so there is no need to
+///   wrap it with `Chunker::max_size()`.
+///
+/// The masks are derived from the average size, which is rounded down to a power of two. Their
+/// 1-bits are spread over the high bits of the fingerprint ("zero padding" in the paper).
+///
+/// Source: Xia, Wen, et al. "FastCDC: A fast and efficient content-defined chunking approach for
+/// data deduplication." 2016 {USENIX} Annual Technical Conference ({USENIX}{ATC} 16). 2016.
+/// PDF: https://www.usenix.org/system/files/conference/atc16/atc16-paper-xia.pdf
+#[derive(Debug, Clone)]
+pub struct FastCDC {
+    min_size: usize,
+    normal_size: usize,
+    max_size: usize,
+    mask_s: u64,
+    mask_l: u64,
+    state: FastCDCState,
+}
+
+impl FastCDC {
+    /// Creates a new FastCDC chunker with the given chunk sizes, using normalization level 2.
+    pub fn new(min_size: usize, avg_size: usize, max_size: usize) -> FastCDC {
+        FastCDC::with_level(min_size, avg_size, max_size, 2)
+    }
+
+    /// Creates a new FastCDC chunker with the given chunk sizes and normalization level (0 to 3).
+    pub fn with_level(min_size: usize, avg_size: usize, max_size: usize, level: u32) -> FastCDC {
+        assert!(min_size > 0, "min_size needs to be at least 1");
+        assert!(
+            min_size <= avg_size && avg_size <= max_size,
+            "sizes need to satisfy min_size <= avg_size <= max_size"
+        );
+        assert!(
+            level <= 3,
+            "normalization level needs to be between 0 and 3"
+        );
+
+        // floor(log2(avg_size))
+        let bits = 63 - (avg_size as u64).leading_zeros();
+        assert!(
+            bits > level && bits + level <= MASK_SPREAD,
+            "avg_size is out of range for this normalization level"
+        );
+
+        FastCDC {
+            min_size,
+            normal_size: avg_size,
+            max_size,
+            mask_s: spread_mask(bits + level),
+            mask_l: spread_mask(bits - level),
+            state: Default::default(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct FastCDCState {
+    /// The fingerprint.
+    hash: u64,
+
+    /// The current position relative to the last chunk boundary.
+    pos: usize,
+}
+
+impl FastCDCState {
+    fn reset(&mut self) {
+        self.hash = 0;
+        self.pos = 0;
+    }
+
+    fn ingest(&mut self, b: u8) {
+        self.hash = (self.hash << 1).wrapping_add(TABLE64[b as usize]);
+    }
+}
+
+impl ChunkerImpl for FastCDC {
+    fn find_boundary(&mut self, data: &[u8]) -> Option<usize> {
+        // Byte `i` of `data` ends a chunk of size `pos + i + 1`.
+        let pos = self.state.pos;
+
+        // Cut-point skipping: don't hash bytes that can't end a chunk.
+        let mut i = (self.min_size - 1).saturating_sub(pos).min(data.len());
+
+        // Use the small mask (more 1-bits) until we reach the average size.
+        let normal_end = self.normal_size.saturating_sub(pos).min(data.len());
+        while i < normal_end {
+            self.state.ingest(data[i]);
+            if self.state.hash & self.mask_s == 0 {
+                return Some(i);
+            }
+            i += 1;
+        }
+
+        // Then the large mask, until we reach the maximum size.
+        let max_end = self.max_size - pos;
+        while i < max_end.min(data.len()) {
+            self.state.ingest(data[i]);
+            if self.state.hash & self.mask_l == 0 {
+                return Some(i);
+            }
+            i += 1;
+        }
+
+        if max_end <= data.len() {
+            return Some(max_end - 1);
+        }
+
+        // No cut-point found within this block of data.
+        self.state.pos += data.len();
+        None
+    }
+
+    fn reset(&mut self) {
+        self.state.reset()
+    }
+}
+
+/// A chunker implementing the FastCDC algorithm, rolling two bytes per iteration.
+///
+/// This produces exactly the same chunks as `FastCDC` with the same parameters, but is faster.
+/// Each iteration of the main loop shifts the fingerprint by two bits at once, using a table of
+/// Gear values shifted left by one bit, and checks the first byte against masks shifted left by
+/// one bit as well. Since the masks never use the most significant bit of the fingerprint, both
+/// checks are equivalent.
+///
+/// Source: Xia, Wen, et al. "The design of fast content-defined chunking for data deduplication
+/// based storage systems." IEEE Transactions on Parallel and Distributed Systems 31.9 (2020):
+/// 2017-2031. https://doi.org/10.1109/TPDS.2020.2984632
+#[derive(Debug, Clone)]
+pub struct FastCDC2020 {
+    inner: FastCDC,
+    mask_s_ls: u64,
+    mask_l_ls: u64,
+    table_ls: [u64; 256],
+}
+
+impl FastCDC2020 {
+    /// Creates a new FastCDC chunker with the given chunk sizes, using normalization level 2.
+    pub fn new(min_size: usize, avg_size: usize, max_size: usize) -> FastCDC2020 {
+        FastCDC2020::with_level(min_size, avg_size, max_size, 2)
+    }
+
+    /// Creates a new FastCDC chunker with the given chunk sizes and normalization level (0 to 3).
+    pub fn with_level(
+        min_size: usize,
+        avg_size: usize,
+        max_size: usize,
+        level: u32,
+    ) -> FastCDC2020 {
+        let inner = FastCDC::with_level(min_size, avg_size, max_size, level);
+        let mut table_ls = [0; 256];
+        for (ls, &v) in table_ls.iter_mut().zip(TABLE64.iter()) {
+            *ls = v << 1;
+        }
+        FastCDC2020 {
+            mask_s_ls: inner.mask_s << 1,
+            mask_l_ls: inner.mask_l << 1,
+            inner,
+            table_ls,
+        }
+    }
+
+    /// Hashes `data[i..end]`, looking for a fingerprint matching `mask`.
+    fn scan(
+        &mut self,
+        data: &[u8],
+        mut i: usize,
+        end: usize,
+        mask: u64,
+        mask_ls: u64,
+    ) -> Option<usize> {
+        let mut hash = self.inner.state.hash;
+        while i + 1 < end {
+            // After this, `hash` is the fingerprint for byte `i`, shifted left by one bit.
+            hash = (hash << 2).wrapping_add(self.table_ls[data[i] as usize]);
+            if hash & mask_ls == 0 {
+                return Some(i);
+            }
+            hash = hash.wrapping_add(TABLE64[data[i + 1] as usize]);
+            if hash & mask == 0 {
+                return Some(i + 1);
+            }
+            i += 2;
+        }
+        self.inner.state.hash = hash;
+
+        // Odd number of bytes, handle the last one normally.
+        if i < end {
+            self.inner.state.ingest(data[i]);
+            if self.inner.state.hash & mask == 0 {
+                return Some(i);
+            }
+        }
+        None
+    }
+}
+
+impl ChunkerImpl for FastCDC2020 {
+    fn find_boundary(&mut self, data: &[u8]) -> Option<usize> {
+        let pos = self.inner.state.pos;
+        let i = (self.inner.min_size - 1)
+            .saturating_sub(pos)
+            .min(data.len());
+
+        let normal_end = self.inner.normal_size.saturating_sub(pos).min(data.len());
+        let (mask_s, mask_s_ls) = (self.inner.mask_s, self.mask_s_ls);
+        if let Some(boundary) = self.scan(data, i, normal_end, mask_s, mask_s_ls) {
+            return Some(boundary);
+        }
+
+        let max_end = self.inner.max_size - pos;
+        let i = i.max(normal_end);
+        let (mask_l, mask_l_ls) = (self.inner.mask_l, self.mask_l_ls);
+        if let Some(boundary) = self.scan(data, i, max_end.min(data.len()), mask_l, mask_l_ls) {
+            return Some(boundary);
+        }
+
+        if max_end <= data.len() {
+            return Some(max_end - 1);
+        }
+
+        self.inner.state.pos += data.len();
+        None
+    }
+
+    fn reset(&mut self) {
+        self.inner.reset()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::{spread_mask, FastCDC, FastCDC2020};
+    use Chunker;
+
+    fn random_data(len: usize) -> Vec<u8> {
+        let mut data = vec![0u8; len];
+        StdRng::seed_from_u64(1).fill(&mut data[..]);
+        data
+    }
+
+    #[test]
+    fn test_masks() {
+        assert_eq!(spread_mask(1), 1 << 62);
+        assert_eq!(spread_mask(13).count_ones(), 13);
+        assert_eq!(spread_mask(48).count_ones(), 48);
+        assert_eq!(spread_mask(48).leading_zeros(), 1);
+    }
+
+    #[test]
+    fn test_sizes() {
+        let data = random_data(1 << 20);
+        let chunks: Vec<_> = Chunker::new(FastCDC::new(1024, 4096, 16384))
+            .slices(&data)
+            .map(|c| c.len())
+            .collect();
+
+        let (last, chunks) = chunks.split_last().unwrap();
+        assert!(*last <= 16384);
+        assert!(chunks.iter().all(|&l| (1024..=16384).contains(&l)));
+        let average = data.len() / (chunks.len() + 1);
+        assert!(3000 < average && average < 6000);
+    }
+
+    #[test]
+    fn test_normalization() {
+        // Higher normalization levels lead to chunk sizes closer to the average
+        let data = random_data(1 << 20);
+        let spread = |level| {
+            let chunker = Chunker::new(FastCDC::with_level(256, 4096, 65536, level));
+            let sizes: Vec<_> = chunker.slices(&data).map(|c| c.len() as f64).collect();
+            let mean = sizes.iter().sum::<f64>() / sizes.len() as f64;
+            sizes.iter().map(|s| (s - mean) * (s - mean)).sum::<f64>() / sizes.len() as f64
+        };
+        assert!(spread(0) > spread(1));
+        assert!(spread(1) > spread(3));
+    }
+
+    #[test]
+    fn test_stream() {
+        // The same boundaries are found when reading from a stream
+        let data = random_data(200_000);
+        let expected: Vec<_> = Chunker::new(FastCDC::new(64, 512, 2048))
+            .slices(&data)
+            .map(|c| c.len())
+            .collect();
+        let result: Vec<_> = Chunker::new(FastCDC::new(64, 512, 2048))
+            .chunks(&data[..])
+            .map(|c| c.unwrap().length())
+            .collect();
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_2020_equivalence() {
+        // Rolling two bytes at a time doesn't change the boundaries
+        let data = random_data(1 << 20);
+        for &(min, avg, max, level) in &[
+            (1024, 4096, 16384, 2),
+            (1, 64, 512, 0),
+            (63, 256, 257, 3),
+            (2048, 8192, 65536, 1),
+        ] {
+            let expected: Vec<_> = Chunker::new(FastCDC::with_level(min, avg, max, level))
+                .slices(&data)
+                .map(|c| c.len())
+                .collect();
+            let result: Vec<_> = Chunker::new(FastCDC2020::with_level(min, avg, max, level))
+                .slices(&data)
+                .map(|c| c.len())
+                .collect();
+            assert_eq!(result, expected);
+            let result: Vec<_> = Chunker::new(FastCDC2020::with_level(min, avg, max, level))
+                .chunks(&data[..])
+                .map(|c| c.unwrap().length())
+                .collect();
+            assert_eq!(result, expected);
+        }
+    }
+}
@@ -23,6 +23,7 @@ impl FixedSizeChunkerState {
 impl FixedSizeChunker {
     /// Constructs a chunker that produces chunks of a fixed, given size.
     pub fn new(chunk_size: usize) -> FixedSizeChunker {
+        assert!(chunk_size > 0, "chunk_size needs to be at least 1");
         FixedSizeChunker {
             chunk_size,
             state: Default::default(),
@@ -32,9 +33,11 @@ impl FixedSizeChunker {
 
 impl ChunkerImpl for FixedSizeChunker {
     fn find_boundary(&mut self, data: &[u8]) -> Option<usize> {
-        if self.state.pos + data.len() >= self.chunk_size {
+        let left = self.chunk_size - self.state.pos;
+        if data.len() >= left {
             // Chunk boundary is within this block.
-            return Some(self.chunk_size - self.state.pos - 1);
+            self.state.reset();
+            return Some(left - 1);
         }
 
         // Chunk boundary does not lie within this block.
@@ -46,3 +49,32 @@ impl ChunkerImpl for FixedSizeChunker {
         self.state.reset()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::FixedSizeChunker;
+    use {Chunker, ChunkerImpl};
+
+    #[test]
+    fn test_fixed_size() {
+        let data = [0u8; 25];
+        let sizes: Vec<_> = Chunker::new(FixedSizeChunker::new(10))
+            .slices(&data)
+            .map(|c| c.len())
+            .collect();
+        assert_eq!(sizes, [10, 10, 5]);
+
+        // Boundaries are found without a reset in between
+        let mut chunker = FixedSizeChunker::new(10);
+        assert_eq!(chunker.find_boundary(&data[..4]), None);
+        assert_eq!(chunker.find_boundary(&data[4..]), Some(5));
+        assert_eq!(chunker.find_boundary(&data[10..]), Some(9));
+        assert_eq!(chunker.find_boundary(&data[20..]), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "chunk_size needs to be at least 1")]
+    fn test_zero_size() {
+        FixedSizeChunker::new(0);
+    }
+}
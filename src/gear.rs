@@ -111,6 +111,11 @@ impl ChunkerImpl for GearChunker {
     fn reset(&mut self) {
         self.state.reset()
     }
+
+    fn window_size(&self) -> Option<usize> {
+        // Older bytes are shifted out of the 32-bit hash
+        Some(32)
+    }
 }
 
 /// A hasher that implements the Gear algorithm with normalized chunking modifications.
@@ -120,6 +125,7 @@ impl ChunkerImpl for GearChunker {
 /// - Chunks smaller or equal in size to the target chunk size use a lower bit mask consisting of
 ///   more 1-bits.
 /// - As soon as the target chunk size is reached, the upper (=smaller) bitmask is used.
+///
 /// This reduces chunk size variability, probably at the cost of deduplication.
 ///
 /// Source: Xia, Wen, et al. "Ddelta: A deduplication-inspired fast delta compression approach."
@@ -0,0 +1,217 @@
+use std::io::{self, Read};
+
+use {ChunkInfo, ChunkInput, ChunkStream, ChunkerImpl, StreamBuffer, BUF_SIZE};
+
+/// An incremental hash function used to fingerprint chunks.
+///
+/// Implementations are provided for `sha2::Sha256`, `blake3::Hasher` and
+/// `xxhash_rust::xxh3::Xxh3` when the `sha2`, `blake3` and `xxhash` features
+/// are enabled.
+pub trait ChunkHasher {
+    /// The digest, as bytes. For integer hashes, this is big-endian.
+    type Digest: AsRef<[u8]>;
+
+    /// Feed more data of the current chunk.
+    fn update(&mut self, data: &[u8]);
+
+    /// Returns the digest of the data fed since the last call, and resets.
+    fn finish(&mut self) -> Self::Digest;
+
+    /// Returns the digest of the given data, which is a whole chunk.
+    fn digest(&mut self, data: &[u8]) -> Self::Digest {
+        self.update(data);
+        self.finish()
+    }
+}
+
+#[cfg(feature = "sha2")]
+impl ChunkHasher for ::sha2::Sha256 {
+    type Digest = [u8; 32];
+
+    fn update(&mut self, data: &[u8]) {
+        ::sha2::Digest::update(self, data)
+    }
+
+    fn finish(&mut self) -> [u8; 32] {
+        ::sha2::Digest::finalize_reset(self).into()
+    }
+}
+
+#[cfg(feature = "blake3")]
+impl ChunkHasher for ::blake3::Hasher {
+    type Digest = [u8; 32];
+
+    fn update(&mut self, data: &[u8]) {
+        ::blake3::Hasher::update(self, data);
+    }
+
+    fn finish(&mut self) -> [u8; 32] {
+        let digest = self.finalize().into();
+        self.reset();
+        digest
+    }
+}
+
+#[cfg(feature = "xxhash")]
+impl ChunkHasher for ::xxhash_rust::xxh3::Xxh3 {
+    type Digest = [u8; 8];
+
+    fn update(&mut self, data: &[u8]) {
+        ::xxhash_rust::xxh3::Xxh3::update(self, data)
+    }
+
+    fn finish(&mut self) -> [u8; 8] {
+        let digest = ::xxhash_rust::xxh3::Xxh3::digest(self).to_be_bytes();
+        self.reset();
+        digest
+    }
+}
+
+/// Iterator on the positions and digests of chunks.
+///
+/// This is created by `Chunker::hashed_chunks()` and
+/// `Chunker::hashed_chunks_with_capacity()`.
+pub struct HashedChunks<R: Read, I: ChunkerImpl, H: ChunkHasher, B: StreamBuffer = [u8; BUF_SIZE]> {
+    stream: ChunkStream<R, I, B>,
+    hasher: H,
+    last_chunk: usize,
+    pos: usize,
+}
+
+impl<R: Read, I: ChunkerImpl, H: ChunkHasher, B: StreamBuffer> HashedChunks<R, I, H, B> {
+    pub(crate) fn new(stream: ChunkStream<R, I, B>, hasher: H) -> HashedChunks<R, I, H, B> {
+        HashedChunks {
+            stream,
+            hasher,
+            last_chunk: 0,
+            pos: 0,
+        }
+    }
+}
+
+impl<R: Read, I: ChunkerImpl, H: ChunkHasher, B: StreamBuffer> Iterator
+    for HashedChunks<R, I, H, B>
+{
+    type Item = io::Result<(ChunkInfo, H::Digest)>;
+
+    fn next(&mut self) -> Option<io::Result<(ChunkInfo, H::Digest)>> {
+        while let Some(chunk) = self.stream.read() {
+            match chunk {
+                Err(e) => return Some(Err(e)),
+                Ok(ChunkInput::Data(d)) => {
+                    self.hasher.update(d);
+                    self.pos += d.len();
+                }
+                Ok(ChunkInput::End) => {
+                    let start = self.last_chunk;
+                    self.last_chunk = self.pos;
+                    let info = ChunkInfo {
+                        start,
+                        length: self.pos - start,
+                    };
+                    return Some(Ok((info, self.hasher.finish())));
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use rand::{self, Rng};
+
+    use super::ChunkHasher;
+    use {Chunker, GearChunker};
+
+    /// FNV-1a, 64 bits.
+    pub struct Fnv(u64);
+
+    impl Fnv {
+        pub fn new() -> Fnv {
+            Fnv(0xcbf2_9ce4_8422_2325)
+        }
+    }
+
+    impl ChunkHasher for Fnv {
+        type Digest = [u8; 8];
+
+        fn update(&mut self, data: &[u8]) {
+            for &b in data {
+                self.0 = (self.0 ^ b as u64).wrapping_mul(0x100_0000_01b3);
+            }
+        }
+
+        fn finish(&mut self) -> [u8; 8] {
+            let digest = self.0.to_be_bytes();
+            self.0 = 0xcbf2_9ce4_8422_2325;
+            digest
+        }
+    }
+
+    #[test]
+    fn test_hashed_chunks() {
+        let mut data = vec![0u8; 1 << 14];
+        rand::thread_rng().fill(&mut data[..]);
+        let chunker = || Chunker::new(GearChunker::new(0xfc00_0000));
+        let fnv = Fnv::new;
+
+        let expected: Vec<_> = chunker()
+            .slices(&data)
+            .map(|c| (c.len(), fnv().digest(c)))
+            .collect();
+        let result: Vec<_> = chunker()
+            .hashed_chunks(&data[..], fnv())
+            .map(|r| {
+                let (info, digest) = r.unwrap();
+                (info.length(), digest)
+            })
+            .collect();
+        assert_eq!(result, expected);
+        let result: Vec<_> = chunker()
+            .hashed_chunks_with_capacity(&data[..], fnv(), 1000)
+            .map(|r| {
+                let (info, digest) = r.unwrap();
+                (info.length(), digest)
+            })
+            .collect();
+        assert_eq!(result, expected);
+        assert_eq!(
+            fnv().digest(b"a"),
+            [0xaf, 0x63, 0xdc, 0x4c, 0x86, 0x01, 0xec, 0x8c]
+        );
+    }
+
+    #[cfg(feature = "sha2")]
+    #[test]
+    fn test_sha2() {
+        let mut hasher = ::sha2::Sha256::default();
+        hasher.update(b"a");
+        hasher.finish();
+        assert_eq!(
+            &hasher.digest(b"abc")[..4],
+            &[0xba, 0x78, 0x16, 0xbf] // ba7816bf...
+        );
+    }
+
+    #[cfg(feature = "blake3")]
+    #[test]
+    fn test_blake3() {
+        let mut hasher = ::blake3::Hasher::new();
+        hasher.update(b"a");
+        hasher.finish();
+        assert_eq!(&hasher.digest(b"")[..4], &[0xaf, 0x13, 0x49, 0xb9]); // af1349b9...
+    }
+
+    #[cfg(feature = "xxhash")]
+    #[test]
+    fn test_xxhash() {
+        let mut hasher = ::xxhash_rust::xxh3::Xxh3::new();
+        hasher.update(b"a");
+        hasher.finish();
+        assert_eq!(
+            ChunkHasher::digest(&mut hasher, b""),
+            [0x2d, 0x06, 0x80, 0x05, 0x38, 0xd3, 0x94, 0xc2]
+        );
+    }
+}
@@ -8,7 +8,11 @@
 //! bytes changed is a multiple of n.
 //!
 //! Content-defined chunking is useful for data de-duplication. It is used in
-//! many backup software, and by the rsync data synchronization tool.
+//! many backup software, and by the rsync data synchronization tool. The
+//! `store` and `delta` modules implement those two uses on top of this crate's
+//! chunkers, and the `analysis` module measures how much a chunking method
+//! deduplicates some data. With the `testing` feature, the `testing` module
+//! checks how chunk boundaries are affected by edits to the data.
 //!
 //! This crate exposes both easy-to-use methods, implementing the standard
 //! `Iterator` trait to iterate on chunks in an input stream, and efficient
@@ -114,29 +118,99 @@
 //!     }
 //! }
 //! ```
+//!
+//! If your reader already implements `BufRead`, `stream_bufread()` works the
+//! same way but returns slices of the reader's own buffer, avoiding a copy.
+//!
+//! ### Fingerprinting chunks
+//!
+//! The `hashed_chunks()` method describes the chunks like `chunks()`, along
+//! with their digest computed by a `ChunkHasher` as the data is read. The
+//! `sha2`, `blake3` and `xxhash` features provide implementations for SHA-256,
+//! BLAKE3 and XXH3.
+//!
+//! ### From an in-memory buffer, using multiple threads
+//!
+//! With the `parallel` feature enabled, `par_slices()` returns the same slices
+//! as `slices()`, chunking different parts of the buffer concurrently. This
+//! feature also requires a more recent compiler.
+//!
+//! ### From an asynchronous reader
+//!
+//! With the `async` feature enabled, the `async_stream()`,
+//! `async_whole_chunks()` and `async_chunks()` methods provide the same
+//! functionality for tokio's `AsyncRead` objects, the last two as
+//! `futures::Stream`s. Note that this feature requires a much more recent
+//! compiler than the rest of the crate.
 
 #![forbid(unsafe_code)]
 
+mod adler;
 mod ae;
+pub mod analysis;
+#[cfg(feature = "async")]
+mod async_read;
 mod bfbc;
+mod bufread;
+mod buzhash;
+mod casync;
+pub mod delta;
+mod fastcdc;
 mod fsc;
 mod gear;
+mod hash;
 mod mii;
+#[cfg(feature = "parallel")]
+mod parallel;
 mod pci;
+mod rabin;
 mod ram;
-
+mod stats;
+pub mod store;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+mod tttd;
+mod varint;
+mod writer;
+mod zpaq;
+
+pub use adler::{AdlerChunker, RollingAdler32};
 pub use ae::AEChunker;
+#[cfg(feature = "async")]
+pub use async_read::{AsyncChunkInfoStream, AsyncChunkStream, AsyncWholeChunks, ReadChunkInput};
 pub use bfbc::BFBCChunker;
+pub use bufread::BufReadChunkStream;
+pub use buzhash::BuzhashChunker;
+pub use casync::CasyncChunker;
+pub use fastcdc::{FastCDC, FastCDC2020};
 pub use fsc::FixedSizeChunker;
 pub use gear::{GearChunker, NormalizedChunkingGearChunker};
+pub use hash::{ChunkHasher, HashedChunks};
 pub use mii::MIIChunker;
 pub use pci::PCIChunker;
+pub use rabin::{Polynomial, RabinChunker, RabinHash, ResticChunker};
 pub use ram::{MaybeOptimizedRAMChunker, RAMChunker};
+pub use stats::{ChunkStats, ChunkStatsStream};
+pub use tttd::TTTDChunker;
+pub use writer::ChunkWriter;
+pub use zpaq::ZPAQCompatible;
+
+#[cfg(feature = "blake3")]
+extern crate blake3;
+#[cfg(feature = "async")]
+extern crate futures_core;
+#[cfg(feature = "sha2")]
+extern crate sha2;
+#[cfg(feature = "async")]
+extern crate tokio;
+#[cfg(feature = "xxhash")]
+extern crate xxhash_rust;
 
 #[cfg(test)]
 extern crate rand;
 
-use std::io::{self, Read};
+use std::cmp::min;
+use std::io::{self, BufRead, Read};
 use std::mem::swap;
 use std::num::Wrapping;
 
@@ -149,6 +223,10 @@ use std::num::Wrapping;
 ///
 /// This is where the internal state of the algorithm should be kept (counter,
 /// hash, etc).
+///
+/// The data can be given to `find_boundary()` in parts of any size, and the
+/// boundaries should not depend on it, unless `lookahead()` says otherwise.
+/// With the `testing` feature, `testing::check_conformance()` checks this.
 pub trait ChunkerImpl {
     /// Look at the new bytes to maybe find a boundary.
     /// The boundary is an index within `data`, after which the cut-point is set.
@@ -158,6 +236,71 @@ pub trait ChunkerImpl {
 
     /// Reset the internal state after a chunk has been emitted
     fn reset(&mut self) {}
+
+    /// The number of bytes the boundaries depend on, if limited.
+    ///
+    /// Implementations should return `Some(n)` if, after a reset, feeding them
+    /// any `n` bytes puts them in the same state as feeding them the whole
+    /// chunk, i.e. whether a boundary is found only depends on the last `n`
+    /// bytes. This allows `Chunker::min_size()` to skip the beginning of chunks.
+    fn window_size(&self) -> Option<usize> {
+        None
+    }
+
+    /// The number of bytes `find_boundary()` needs to be given at once, if any.
+    ///
+    /// Some methods can only choose a boundary after looking at the bytes that
+    /// follow it, for example `TTTDChunker` going back to a backup breakpoint.
+    /// Implementations should return `Some(n)` if, after a reset, they always
+    /// find a boundary in the first `n` bytes when given them in a single call.
+    /// The streams then keep the data of each chunk in memory until they have
+    /// `n` bytes or reach the end of the input, so that the boundaries don't
+    /// depend on the read sizes.
+    ///
+    /// This applies to all the streams and `ChunkWriter`, and `SizeLimited` and
+    /// `MinSizeLimited` adjust it. Each stream then keeps up to `n` bytes, plus
+    /// one read, in memory. `Chunker::slices()` always gives the whole rest of
+    /// the buffer, so it doesn't need this.
+    fn lookahead(&self) -> Option<usize> {
+        None
+    }
+}
+
+/// Allows choosing the chunking method at runtime, using `Box<dyn ChunkerImpl>`.
+impl<I: ChunkerImpl + ?Sized> ChunkerImpl for Box<I> {
+    fn find_boundary(&mut self, data: &[u8]) -> Option<usize> {
+        (**self).find_boundary(data)
+    }
+
+    fn reset(&mut self) {
+        (**self).reset()
+    }
+
+    fn window_size(&self) -> Option<usize> {
+        (**self).window_size()
+    }
+
+    fn lookahead(&self) -> Option<usize> {
+        (**self).lookahead()
+    }
+}
+
+/// A rolling hash over a fixed-size window of bytes.
+///
+/// This can be used by chunkers that are not tied to a specific hash function,
+/// such as `TTTDChunker`.
+pub trait RollingHash {
+    /// The number of bytes the hash depends on.
+    fn window_size(&self) -> usize;
+
+    /// Add a byte to the window, removing the oldest one once it is full.
+    fn slide(&mut self, byte: u8);
+
+    /// The hash of the current window.
+    fn digest(&self) -> u64;
+
+    /// Reset to the initial state, as if the window was filled with zeros.
+    fn reset(&mut self);
 }
 
 #[cfg(not(test))]
@@ -184,6 +327,21 @@ impl<I: ChunkerImpl> Chunker<I> {
         }
     }
 
+    /// Iterates on whole chunks, reading into a buffer of the given size.
+    ///
+    /// This is the same as `whole_chunks()`, with the buffer of
+    /// `stream_with_capacity()`.
+    pub fn whole_chunks_with_capacity<R: Read>(
+        self,
+        reader: R,
+        capacity: usize,
+    ) -> WholeChunks<R, I, Box<[u8]>> {
+        WholeChunks {
+            stream: self.stream_with_capacity(reader, capacity),
+            buffer: Vec::new(),
+        }
+    }
+
     /// Reads all the chunks at once, in a vector of chunks (also vectors).
     ///
     /// This is similar to `.whole_chunks().collect()`, but takes care of the IO
@@ -226,16 +384,112 @@ impl<I: ChunkerImpl> Chunker<I> {
     /// }
     /// ```
     pub fn stream<R: Read>(self, reader: R) -> ChunkStream<R, I> {
+        self.stream_with_buffer(reader, [0u8; BUF_SIZE])
+    }
+
+    /// Reads chunks using a heap-allocated buffer of the given size.
+    ///
+    /// This is the same as `stream()`, but the buffer is allocated once with
+    /// the given capacity instead of being part of the `ChunkStream` object.
+    /// Larger buffers mean fewer calls to `read()`, which is faster with large
+    /// files.
+    pub fn stream_with_capacity<R: Read>(
+        self,
+        reader: R,
+        capacity: usize,
+    ) -> ChunkStream<R, I, Box<[u8]>> {
+        self.stream_with_buffer(reader, vec![0u8; capacity].into_boxed_slice())
+    }
+
+    /// Reads chunks using a buffer provided by the caller.
+    ///
+    /// This is the same as `stream()`, but reads into the given buffer, which
+    /// can be an array, a `Vec<u8>` or `Box<[u8]>`, or a mutable slice. The
+    /// buffer can be used again after calling `ChunkStream::into_buffer()`.
+    pub fn stream_with_buffer<R: Read, B: StreamBuffer>(
+        self,
+        reader: R,
+        buffer: B,
+    ) -> ChunkStream<R, I, B> {
+        assert!(!buffer.buffer().is_empty(), "buffer can't be empty");
         ChunkStream {
             reader,
-            inner: self.inner,
-            buffer: [0u8; BUF_SIZE],
-            pos: 0,
-            len: 0,
-            status: EmitStatus::Data,
+            buffer,
+            splitter: Splitter::new(self.inner),
         }
     }
 
+    /// Reads chunks directly from the buffer of a `BufRead` object.
+    ///
+    /// This is the same as `stream()`, but instead of copying the data into
+    /// its own buffer, it returns slices of the reader's buffer. Use this if
+    /// you already have a `BufReader`, or an in-memory reader such as `&[u8]`.
+    pub fn stream_bufread<R: BufRead>(self, reader: R) -> BufReadChunkStream<R, I> {
+        BufReadChunkStream::new(reader, self.inner)
+    }
+
+    /// Reads chunks asynchronously with zero allocations.
+    ///
+    /// This is the same as `stream()`, for tokio's `AsyncRead`.
+    #[cfg(feature = "async")]
+    pub fn async_stream<R: tokio::io::AsyncRead + Unpin>(
+        self,
+        reader: R,
+    ) -> AsyncChunkStream<R, I> {
+        AsyncChunkStream::new(reader, self.inner)
+    }
+
+    /// Asynchronously reads whole chunks into new vectors.
+    ///
+    /// This is the same as `whole_chunks()`, for tokio's `AsyncRead`, as a
+    /// `futures::Stream`.
+    #[cfg(feature = "async")]
+    pub fn async_whole_chunks<R: tokio::io::AsyncRead + Unpin>(
+        self,
+        reader: R,
+    ) -> AsyncWholeChunks<R, I> {
+        AsyncWholeChunks::new(self.async_stream(reader))
+    }
+
+    /// Asynchronously describes the chunks (don't return the data).
+    ///
+    /// This is the same as `chunks()`, for tokio's `AsyncRead`, as a
+    /// `futures::Stream`.
+    #[cfg(feature = "async")]
+    pub fn async_chunks<R: tokio::io::AsyncRead + Unpin>(
+        self,
+        reader: R,
+    ) -> AsyncChunkInfoStream<R, I> {
+        AsyncChunkInfoStream::new(self.async_stream(reader))
+    }
+
+    /// Splits the data written to the returned object into chunks.
+    ///
+    /// This is the opposite of `stream()`: instead of pulling data from a
+    /// reader, the data is pushed through the `Write` trait, and the `sink` is
+    /// called with the same `ChunkInput` items. Call `finish()` to end the
+    /// last chunk.
+    ///
+    /// Example:
+    ///
+    /// ```
+    /// # use cdchunking::{Chunker, ChunkInput, ZPAQ};
+    /// # use std::io::Write;
+    /// # let chunker = Chunker::new(ZPAQ::new(13));
+    /// let mut writer = chunker.writer(|chunk| {
+    ///     match chunk {
+    ///         ChunkInput::Data(d) => print!("{:?}, ", d),
+    ///         ChunkInput::End => println!(" end of chunk"),
+    ///     }
+    ///     Ok(())
+    /// });
+    /// writer.write_all(b"abcdefghijklmnopqrstuvwxyz1234567890").unwrap();
+    /// writer.finish().unwrap();
+    /// ```
+    pub fn writer<F: FnMut(ChunkInput) -> io::Result<()>>(self, sink: F) -> ChunkWriter<I, F> {
+        ChunkWriter::new(self.inner, sink)
+    }
+
     /// Describes the chunks (don't return the data).
     ///
     /// This iterator gives you the offset and size of the chunks, but not the
@@ -249,6 +503,79 @@ impl<I: ChunkerImpl> Chunker<I> {
         }
     }
 
+    /// Describes the chunks, reading into a buffer of the given size.
+    ///
+    /// This is the same as `chunks()`, with the buffer of
+    /// `stream_with_capacity()`.
+    pub fn chunks_with_capacity<R: Read>(
+        self,
+        reader: R,
+        capacity: usize,
+    ) -> ChunkInfoStream<R, I, Box<[u8]>> {
+        ChunkInfoStream {
+            stream: self.stream_with_capacity(reader, capacity),
+            last_chunk: 0,
+            pos: 0,
+        }
+    }
+
+    /// Describes the chunks, with a digest of their data.
+    ///
+    /// This is similar to `chunks()`, but the data is also fed to the given
+    /// hasher as it is read, so that a digest of each chunk is computed
+    /// without ever holding a whole chunk in memory.
+    ///
+    /// Example:
+    ///
+    /// ```
+    /// # use cdchunking::{Chunker, ChunkHasher, ZPAQ};
+    /// # let chunker = Chunker::new(ZPAQ::new(13));
+    /// # let reader: &[u8] = b"abcdefghijklmnopqrstuvwxyz1234567890";
+    /// struct Sum(u8);
+    ///
+    /// impl ChunkHasher for Sum {
+    ///     type Digest = [u8; 1];
+    ///
+    ///     fn update(&mut self, data: &[u8]) {
+    ///         for &b in data {
+    ///             self.0 = self.0.wrapping_add(b);
+    ///         }
+    ///     }
+    ///
+    ///     fn finish(&mut self) -> [u8; 1] {
+    ///         let digest = [self.0];
+    ///         self.0 = 0;
+    ///         digest
+    ///     }
+    /// }
+    ///
+    /// for chunk in chunker.hashed_chunks(reader, Sum(0)) {
+    ///     let (info, digest) = chunk.unwrap();
+    ///     println!("{} {} {:?}", info.start(), info.length(), digest);
+    /// }
+    /// ```
+    pub fn hashed_chunks<R: Read, H: ChunkHasher>(
+        self,
+        reader: R,
+        hasher: H,
+    ) -> HashedChunks<R, I, H> {
+        HashedChunks::new(self.stream(reader), hasher)
+    }
+
+    /// Describes the chunks with their digest, reading into a buffer of the
+    /// given size.
+    ///
+    /// This is the same as `hashed_chunks()`, with the buffer of
+    /// `stream_with_capacity()`.
+    pub fn hashed_chunks_with_capacity<R: Read, H: ChunkHasher>(
+        self,
+        reader: R,
+        hasher: H,
+        capacity: usize,
+    ) -> HashedChunks<R, I, H, Box<[u8]>> {
+        HashedChunks::new(self.stream_with_capacity(reader, capacity), hasher)
+    }
+
     /// Iterate on chunks in an in-memory buffer as slices.
     ///
     /// If your data is already in memory, you can use this method instead of
@@ -274,14 +601,49 @@ impl<I: ChunkerImpl> Chunker<I> {
             inner: SizeLimited::new(self.inner, max),
         }
     }
+
+    /// Returns a new `Chunker` object that will not emit chunks under a size.
+    ///
+    /// Boundaries found by the inner chunking method before `min` bytes are
+    /// ignored. If the inner method has a limited window (see
+    /// `ChunkerImpl::window_size()`), the beginning of chunks is not even
+    /// hashed, only enough bytes to fill the window before the minimum size.
+    /// Otherwise, the inner method IS reset when a boundary gets ignored.
+    ///
+    /// Note that the end of the input can still produce a smaller chunk.
+    pub fn min_size(self, min: usize) -> Chunker<MinSizeLimited<I>> {
+        Chunker {
+            inner: MinSizeLimited::new(self.inner, min),
+        }
+    }
+}
+
+#[cfg(feature = "parallel")]
+impl<I: ChunkerImpl + Clone + Send> Chunker<I> {
+    /// Chunks an in-memory buffer using multiple threads.
+    ///
+    /// This returns exactly the same slices as `slices()`. The buffer is split
+    /// in one segment per thread, which are chunked concurrently as if a chunk
+    /// started at the beginning of each segment. The boundaries are then
+    /// resynchronized: at the start of each segment, chunks are computed again
+    /// from the actual end of the previous chunk, until one of the boundaries
+    /// found by the thread is reached. With content-defined chunking, this
+    /// happens after a few chunks.
+    ///
+    /// The chunking method's state after `reset()` has to only depend on the
+    /// data that follows; this is the case for all the methods in this crate
+    /// that implement `Clone`.
+    pub fn par_slices(self, buffer: &[u8], threads: usize) -> Vec<&[u8]> {
+        parallel::par_slices(self.inner, buffer, threads)
+    }
 }
 
-pub struct WholeChunks<R: Read, I: ChunkerImpl> {
-    stream: ChunkStream<R, I>,
+pub struct WholeChunks<R: Read, I: ChunkerImpl, B: StreamBuffer = [u8; BUF_SIZE]> {
+    stream: ChunkStream<R, I, B>,
     buffer: Vec<u8>,
 }
 
-impl<R: Read, I: ChunkerImpl> Iterator for WholeChunks<R, I> {
+impl<R: Read, I: ChunkerImpl, B: StreamBuffer> Iterator for WholeChunks<R, I, B> {
     type Item = io::Result<Vec<u8>>;
 
     fn next(&mut self) -> Option<io::Result<Vec<u8>>> {
@@ -316,55 +678,263 @@ enum EmitStatus {
     AtSplit, // We found the end of a chunk, emitted the Data but not the End
 }
 
-pub struct ChunkStream<R: Read, I: ChunkerImpl> {
-    reader: R,
+/// Holds the data of the current chunk, for chunkers that have a lookahead.
+///
+/// The streams copy what they read here until there are enough bytes to call
+/// `find_boundary()`, see `ChunkerImpl::lookahead()`.
+struct Lookahead {
+    size: usize,
+    data: Vec<u8>,
+    start: usize,   // Where the data that wasn't returned yet starts
+    emitted: usize, // How much of it was returned, to skip on the next call
+    at_split: bool, // Whether the data returned ended a chunk
+    eof: bool,
+}
+
+impl Lookahead {
+    fn new(size: usize) -> Lookahead {
+        Lookahead {
+            size,
+            data: Vec::new(),
+            start: 0,
+            emitted: 0,
+            at_split: false,
+            eof: false,
+        }
+    }
+
+    /// Whether more data should be pushed before calling `next()`.
+    fn needs_data(&self) -> bool {
+        !self.eof && !self.at_split && self.data.len() - self.start - self.emitted < self.size
+    }
+
+    /// Adds data read from the input, empty meaning the end.
+    fn push(&mut self, data: &[u8]) {
+        if data.is_empty() {
+            self.eof = true;
+        } else {
+            // Only move the remaining data to the front once it is shorter
+            // than what was returned, so each byte is moved a bounded number
+            // of times
+            self.start += self.emitted;
+            self.emitted = 0;
+            if self.start >= self.data.len() - self.start {
+                self.data.drain(..self.start);
+                self.start = 0;
+            }
+            self.data.extend_from_slice(data);
+        }
+    }
+
+    /// Returns the next item, or `None` if more data is needed or all of it
+    /// was returned.
+    fn next<I: ChunkerImpl>(&mut self, inner: &mut I) -> Option<ChunkInput<'_>> {
+        self.start += self.emitted;
+        self.emitted = 0;
+        if self.at_split {
+            self.at_split = false;
+            inner.reset();
+            return Some(ChunkInput::End);
+        }
+        let data = &self.data[self.start..];
+        if self.needs_data() || data.is_empty() {
+            return None;
+        }
+        match inner.find_boundary(data) {
+            Some(split) => {
+                assert!(split < data.len());
+                self.at_split = true;
+                self.emitted = split + 1;
+            }
+            None => {
+                assert!(self.eof, "no boundary found within lookahead()");
+                self.emitted = data.len();
+            }
+        }
+        Some(ChunkInput::Data(&data[..self.emitted]))
+    }
+
+    /// Same as `next()`, but also returns `End` after the last chunk, like
+    /// `ChunkStream::read()` does.
+    fn step<I: ChunkerImpl>(
+        &mut self,
+        inner: &mut I,
+        status: &mut EmitStatus,
+    ) -> Option<ChunkInput<'_>> {
+        match self.next(inner) {
+            Some(ChunkInput::End) => {
+                *status = EmitStatus::End;
+                Some(ChunkInput::End)
+            }
+            Some(data) => {
+                *status = EmitStatus::Data;
+                Some(data)
+            }
+            None if *status == EmitStatus::Data => {
+                *status = EmitStatus::End;
+                Some(ChunkInput::End)
+            }
+            None => None,
+        }
+    }
+}
+
+/// The state machine splitting the contents of a buffer into chunks.
+///
+/// This is shared by the streams over `Read` and `AsyncRead` objects, which
+/// fill the buffer when `needs_read()` returns true.
+struct Splitter<I: ChunkerImpl> {
     inner: I,
-    buffer: [u8; BUF_SIZE],
     len: usize, // How much of the buffer has been read in from the reader
     pos: usize, // Where are we in handling the buffer
     status: EmitStatus,
+    lookahead: Option<Lookahead>, // If set, the data is copied there instead
 }
 
-impl<R: Read, I: ChunkerImpl> ChunkStream<R, I> {
-    /// Iterate on the chunks, returning `ChunkInput` items.
-    ///
-    /// An item is either some data that is part of the current chunk, or `End`,
-    /// indicating the boundary between chunks.
-    ///
-    /// `End` is always returned at the end of the last chunk.
-    // Can't be Iterator because of 'a
-    pub fn read<'a>(&'a mut self) -> Option<io::Result<ChunkInput<'a>>> {
+impl<I: ChunkerImpl> Splitter<I> {
+    fn new(inner: I) -> Splitter<I> {
+        let lookahead = inner.lookahead().map(Lookahead::new);
+        Splitter {
+            inner,
+            pos: 0,
+            len: 0,
+            status: EmitStatus::Data,
+            lookahead,
+        }
+    }
+
+    /// Whether the buffer should be filled before calling `step()`.
+    fn needs_read(&self) -> bool {
+        match self.lookahead {
+            Some(ref lookahead) => lookahead.needs_data(),
+            None => self.status != EmitStatus::AtSplit && self.pos == self.len,
+        }
+    }
+
+    /// Records the data that was read into the buffer, empty meaning the end.
+    fn filled(&mut self, data: &[u8]) {
+        if let Some(ref mut lookahead) = self.lookahead {
+            lookahead.push(data);
+            return;
+        }
+        self.pos = 0;
+        self.len = data.len();
+    }
+
+    fn step<'a>(&'a mut self, buffer: &'a [u8]) -> Option<ChunkInput<'a>> {
+        if let Some(ref mut lookahead) = self.lookahead {
+            return lookahead.step(&mut self.inner, &mut self.status);
+        }
         if self.status == EmitStatus::AtSplit {
             self.status = EmitStatus::End;
             self.inner.reset();
-            return Some(Ok(ChunkInput::End));
+            return Some(ChunkInput::End);
         }
-        if self.pos == self.len {
-            assert!(self.status != EmitStatus::AtSplit);
-            self.pos = 0;
-            self.len = match self.reader.read(&mut self.buffer) {
-                Ok(l) => l,
-                Err(e) => return Some(Err(e)),
-            };
-            if self.len == 0 {
-                if self.status == EmitStatus::Data {
-                    self.status = EmitStatus::End;
-                    return Some(Ok(ChunkInput::End));
-                }
-                return None;
+        if self.len == 0 {
+            if self.status == EmitStatus::Data {
+                self.status = EmitStatus::End;
+                return Some(ChunkInput::End);
             }
+            return None;
         }
-        if let Some(split) = self.inner.find_boundary(&self.buffer[self.pos..self.len]) {
+        if let Some(split) = self.inner.find_boundary(&buffer[self.pos..self.len]) {
             assert!(self.pos + split < self.len);
             self.status = EmitStatus::AtSplit;
             let start = self.pos;
             self.pos += split + 1;
-            return Some(Ok(ChunkInput::Data(&self.buffer[start..self.pos])));
+            return Some(ChunkInput::Data(&buffer[start..self.pos]));
         }
         let start = self.pos;
         self.pos = self.len;
         self.status = EmitStatus::Data;
-        Some(Ok(ChunkInput::Data(&self.buffer[start..self.len])))
+        Some(ChunkInput::Data(&buffer[start..self.len]))
+    }
+}
+
+/// A buffer `ChunkStream` can read into.
+///
+/// This is implemented for `Vec<u8>`, `Box<[u8]>`, mutable slices, and the
+/// array type used by `Chunker::stream()`.
+pub trait StreamBuffer {
+    /// The buffer's contents, the data is read from there.
+    fn buffer(&self) -> &[u8];
+
+    /// The buffer to read into; its whole length is used.
+    fn buffer_mut(&mut self) -> &mut [u8];
+}
+
+impl StreamBuffer for [u8; BUF_SIZE] {
+    fn buffer(&self) -> &[u8] {
+        self
+    }
+
+    fn buffer_mut(&mut self) -> &mut [u8] {
+        self
+    }
+}
+
+impl StreamBuffer for Vec<u8> {
+    fn buffer(&self) -> &[u8] {
+        self
+    }
+
+    fn buffer_mut(&mut self) -> &mut [u8] {
+        self
+    }
+}
+
+impl StreamBuffer for Box<[u8]> {
+    fn buffer(&self) -> &[u8] {
+        self
+    }
+
+    fn buffer_mut(&mut self) -> &mut [u8] {
+        self
+    }
+}
+
+impl StreamBuffer for &mut [u8] {
+    fn buffer(&self) -> &[u8] {
+        self
+    }
+
+    fn buffer_mut(&mut self) -> &mut [u8] {
+        self
+    }
+}
+
+pub struct ChunkStream<R: Read, I: ChunkerImpl, B: StreamBuffer = [u8; BUF_SIZE]> {
+    reader: R,
+    buffer: B,
+    splitter: Splitter<I>,
+}
+
+impl<R: Read, I: ChunkerImpl, B: StreamBuffer> ChunkStream<R, I, B> {
+    /// Iterate on the chunks, returning `ChunkInput` items.
+    ///
+    /// An item is either some data that is part of the current chunk, or `End`,
+    /// indicating the boundary between chunks.
+    ///
+    /// `End` is always returned at the end of the last chunk.
+    // Can't be Iterator because of 'a
+    pub fn read<'a>(&'a mut self) -> Option<io::Result<ChunkInput<'a>>> {
+        while self.splitter.needs_read() {
+            match self.reader.read(self.buffer.buffer_mut()) {
+                Ok(l) => {
+                    self.splitter.filled(&self.buffer.buffer()[..l]);
+                    if l == 0 {
+                        break;
+                    }
+                }
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        self.splitter.step(self.buffer.buffer()).map(Ok)
+    }
+
+    /// Returns the buffer, so that it can be used again.
+    pub fn into_buffer(self) -> B {
+        self.buffer
     }
 }
 
@@ -387,13 +957,33 @@ impl ChunkInfo {
     }
 }
 
-pub struct ChunkInfoStream<R: Read, I: ChunkerImpl> {
-    stream: ChunkStream<R, I>,
+pub struct ChunkInfoStream<R: Read, I: ChunkerImpl, B: StreamBuffer = [u8; BUF_SIZE]> {
+    stream: ChunkStream<R, I, B>,
     last_chunk: usize,
     pos: usize,
 }
 
-impl<R: Read, I: ChunkerImpl> Iterator for ChunkInfoStream<R, I> {
+impl<R: Read, I: ChunkerImpl, B: StreamBuffer> ChunkInfoStream<R, I, B> {
+    /// Also collects statistics on the size of the chunks.
+    ///
+    /// ```
+    /// # use cdchunking::{Chunker, ZPAQ};
+    /// # let chunker = Chunker::new(ZPAQ::new(13));
+    /// # let reader: &[u8] = b"abcdefghijklmnopqrstuvwxyz1234567890";
+    /// let mut chunks = chunker.chunks(reader).with_stats();
+    /// for chunk in &mut chunks {
+    ///     let chunk = chunk.expect("Error reading from file");
+    ///     println!("{} {}", chunk.start(), chunk.length());
+    /// }
+    /// let stats = chunks.into_stats();
+    /// println!("average size: {}, median: {:?}", stats.mean(), stats.percentile(50.0));
+    /// ```
+    pub fn with_stats(self) -> ChunkStatsStream<R, I, B> {
+        ChunkStatsStream::new(self)
+    }
+}
+
+impl<R: Read, I: ChunkerImpl, B: StreamBuffer> Iterator for ChunkInfoStream<R, I, B> {
     type Item = io::Result<ChunkInfo>;
 
     fn next(&mut self) -> Option<io::Result<ChunkInfo>> {
@@ -447,6 +1037,7 @@ impl<'a, I: ChunkerImpl> Iterator for Slices<'a, I> {
 /// emitted because of the size limit. This will generally reduce content-dependence,
 /// and thus deduplication ratio, because the boundary is set by size rather than by
 /// content.
+#[derive(Clone)]
 pub struct SizeLimited<I: ChunkerImpl> {
     inner: I,
     pos: usize,
@@ -500,6 +1091,95 @@ impl<I: ChunkerImpl> ChunkerImpl for SizeLimited<I> {
         self.pos = 0;
         self.inner.reset();
     }
+
+    fn lookahead(&self) -> Option<usize> {
+        self.inner.lookahead().map(|n| min(n, self.max_size))
+    }
+}
+
+/// Wrapper for a chunker that ignores boundaries before a minimum size.
+///
+/// This is created by `Chunker::min_size()`.
+#[derive(Clone)]
+pub struct MinSizeLimited<I: ChunkerImpl> {
+    inner: I,
+    pos: usize,
+    min_size: usize,
+}
+
+impl<I: ChunkerImpl> MinSizeLimited<I> {
+    /// Wraps the given chunker implementation to set a minimum chunk size.
+    pub fn new(inner: I, min_size: usize) -> Self {
+        MinSizeLimited {
+            inner,
+            pos: 0,
+            min_size,
+        }
+    }
+}
+
+impl<I: ChunkerImpl> ChunkerImpl for MinSizeLimited<I> {
+    fn find_boundary(&mut self, data: &[u8]) -> Option<usize> {
+        let window_size = self.inner.window_size();
+        let mut start = 0;
+
+        // Skip the bytes that can't influence the boundaries after the minimum
+        if let Some(window_size) = window_size {
+            let skip = self
+                .min_size
+                .saturating_sub(window_size)
+                .saturating_sub(self.pos);
+            start = min(skip, data.len());
+            self.pos += start;
+        }
+
+        // Ignore the boundaries found before the minimum
+        while self.pos + 1 < self.min_size && start < data.len() {
+            let end = min(start + self.min_size - self.pos, data.len());
+            match self.inner.find_boundary(&data[start..end]) {
+                Some(p) => {
+                    self.pos += p + 1;
+                    if self.pos >= self.min_size {
+                        return Some(start + p);
+                    }
+                    // A limited window means the state doesn't depend on where
+                    // chunks start, so there is no need to reset
+                    if window_size.is_none() {
+                        self.inner.reset();
+                    }
+                    start += p + 1;
+                }
+                None => {
+                    self.pos += end - start;
+                    start = end;
+                }
+            }
+        }
+
+        if start == data.len() {
+            return None;
+        }
+        match self.inner.find_boundary(&data[start..]) {
+            Some(p) => {
+                self.pos += p + 1;
+                Some(start + p)
+            }
+            None => {
+                self.pos += data.len() - start;
+                None
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        self.pos = 0;
+        self.inner.reset();
+    }
+
+    fn lookahead(&self) -> Option<usize> {
+        // The boundaries before the minimum are skipped
+        self.inner.lookahead().map(|n| n + self.min_size)
+    }
 }
 
 const HM: Wrapping<u32> = Wrapping(123_456_791);
@@ -511,7 +1191,8 @@ const HM: Wrapping<u32> = Wrapping(123_456_791);
 /// sizes, and does not enforce chunk size limits (unless you use
 /// `Chunker::max_size()` explicitly). In addition, the constants used by this
 /// implementation are different; see
-/// [#6](https://github.com/remram44/cdchunking-rs/issues/6).
+/// [#6](https://github.com/remram44/cdchunking-rs/issues/6). Use
+/// `ZPAQCompatible` to get the same fragments as the reference implementation.
 pub struct ZPAQ {
     nbits: usize,
     c1: u8, // previous byte
@@ -568,7 +1249,7 @@ mod tests {
     use std::io::{self, Read};
     use std::str::from_utf8;
 
-    use super::{ChunkInput, Chunker, ZPAQ};
+    use super::{ChunkInput, ChunkStream, Chunker, ChunkerImpl, GearChunker, StreamBuffer, ZPAQ};
 
     fn base() -> (
         Chunker<ZPAQ>,
@@ -631,6 +1312,48 @@ mod tests {
         assert_eq!(from_utf8(&result).unwrap(), from_utf8(&expected).unwrap());
     }
 
+    #[test]
+    fn test_stream_with_buffer() {
+        let (_, data, _, expected) = base();
+        fn collect<B: StreamBuffer>(mut chunk_iter: ChunkStream<&[u8], ZPAQ, B>) -> (Vec<u8>, B) {
+            let mut result = Vec::new();
+            while let Some(chunk) = chunk_iter.read() {
+                match chunk.unwrap() {
+                    ChunkInput::Data(d) => result.extend(d),
+                    ChunkInput::End => result.push(b'|'),
+                }
+            }
+            (result, chunk_iter.into_buffer())
+        }
+
+        // Heap-allocated buffers, smaller and larger than the input
+        for &capacity in &[1, 5, 1000] {
+            let chunk_iter = Chunker::new(ZPAQ::new(3)).stream_with_capacity(data, capacity);
+            let (result, buffer) = collect(chunk_iter);
+            assert_eq!(from_utf8(&result).unwrap(), from_utf8(expected).unwrap());
+            assert_eq!(buffer.len(), capacity);
+        }
+
+        // Buffer provided by the caller
+        let mut buffer = [0u8; 3];
+        let chunk_iter = Chunker::new(ZPAQ::new(3)).stream_with_buffer(data, &mut buffer[..]);
+        let (result, _) = collect(chunk_iter);
+        assert_eq!(from_utf8(&result).unwrap(), from_utf8(expected).unwrap());
+
+        // The other iterators
+        let chunks = Chunker::new(ZPAQ::new(3))
+            .whole_chunks_with_capacity(data, 5)
+            .map(|c| c.unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(chunks, Chunker::new(ZPAQ::new(3)).all_chunks(data).unwrap());
+        let lengths: Vec<_> = Chunker::new(ZPAQ::new(3))
+            .chunks_with_capacity(data, 5)
+            .with_stats()
+            .map(|c| c.unwrap().length())
+            .collect();
+        assert_eq!(lengths, vec![3, 5, 4, 2, 6, 6, 7]);
+    }
+
     #[test]
     fn test_slices() {
         let (chunker, data, _, expected) = base();
@@ -689,6 +1412,56 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_min_size() {
+        let (chunker, data, reader, _) = base();
+        let mut result = Vec::new();
+
+        // Get chunk positions
+        for chunk_info in chunker.min_size(5).chunks(reader) {
+            let chunk_info = chunk_info.unwrap();
+            result.push((chunk_info.start(), chunk_info.length()));
+        }
+        // ZPAQ doesn't have a limited window, so it is reset when a boundary
+        // is ignored
+        assert_eq!(result, vec![(0, 8), (8, 6), (14, 6), (20, 6), (26, 7)]);
+
+        let (chunker, _, _, _) = base();
+        let sizes: Vec<_> = chunker.min_size(5).slices(data).map(|c| c.len()).collect();
+        assert_eq!(sizes, vec![8, 6, 6, 6, 7]);
+    }
+
+    #[test]
+    fn test_min_size_window() {
+        let mut data = vec![0u8; 1 << 16];
+        rand::thread_rng().fill(&mut data[..]);
+        let chunker = || Chunker::new(GearChunker::new(0xfe00_0000)).min_size(300);
+
+        // Find the first boundary after 300 bytes, hashing the whole chunk
+        let mut expected = Vec::new();
+        let mut start = 0;
+        while start < data.len() {
+            let mut gear = GearChunker::new(0xfe00_0000);
+            let mut end = data.len();
+            for i in start..data.len() {
+                if gear.find_boundary(&data[i..i + 1]).is_some() && i + 1 - start >= 300 {
+                    end = i + 1;
+                    break;
+                }
+            }
+            expected.push(end - start);
+            start = end;
+        }
+
+        let result: Vec<_> = chunker().slices(&data).map(|c| c.len()).collect();
+        assert_eq!(result, expected);
+        let result: Vec<_> = chunker()
+            .chunks(&data[..])
+            .map(|c| c.unwrap().length())
+            .collect();
+        assert_eq!(result, expected);
+    }
+
     struct RngFile<R: Rng>(R);
 
     impl<R: Rng> Read for RngFile<R> {
@@ -0,0 +1,146 @@
+// This feature requires a more recent compiler than the rest of the crate
+#![allow(clippy::incompatible_msrv)]
+
+use std::panic;
+use std::thread;
+
+use ChunkerImpl;
+
+/// Finds the end of the chunk starting at `pos`, like `Slices` does.
+fn next_boundary<I: ChunkerImpl>(inner: &mut I, buffer: &[u8], pos: usize) -> usize {
+    match inner.find_boundary(&buffer[pos..]) {
+        Some(split) => {
+            assert!(pos + split < buffer.len());
+            inner.reset();
+            pos + split + 1
+        }
+        None => buffer.len(),
+    }
+}
+
+/// Chunks a buffer using multiple threads, see `Chunker::par_slices()`.
+///
+/// The buffer is divided into segments, and each thread chunks one segment as
+/// if a chunk started at its beginning. The boundaries it finds are returned
+/// with that starting position, until it reaches the next segment.
+///
+/// Those boundaries are then followed from the start of the buffer. When the
+/// end of a chunk is not one of the boundaries found in its segment, chunks are
+/// computed sequentially until it is. From there, the chunker is in the same
+/// state as in the thread that found that boundary, so its results are used.
+pub(crate) fn par_slices<I: ChunkerImpl + Clone + Send>(
+    inner: I,
+    buffer: &[u8],
+    threads: usize,
+) -> Vec<&[u8]> {
+    let threads = if threads == 0 { 1 } else { threads };
+    let mut starts: Vec<usize> = (0..threads).map(|k| buffer.len() / threads * k).collect();
+    starts.dedup();
+
+    // Boundaries found by each thread, starting with its segment's start
+    let segments: Vec<Vec<usize>> = thread::scope(|scope| {
+        let handles: Vec<_> = starts
+            .iter()
+            .enumerate()
+            .map(|(k, &start)| {
+                let mut chunker = inner.clone();
+                if k > 0 {
+                    chunker.reset();
+                }
+                let stop = starts.get(k + 1).cloned().unwrap_or(buffer.len());
+                scope.spawn(move || {
+                    let mut boundaries = vec![start];
+                    let mut pos = start;
+                    while pos < stop {
+                        pos = next_boundary(&mut chunker, buffer, pos);
+                        boundaries.push(pos);
+                    }
+                    boundaries
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|h| h.join().unwrap_or_else(|e| panic::resume_unwind(e)))
+            .collect()
+    });
+
+    // Follow the boundaries, resynchronizing with the threads' results
+    let mut chunker = inner;
+    chunker.reset();
+    let mut ends = Vec::new();
+    let mut pos = 0;
+    while pos < buffer.len() {
+        let segment = match starts.binary_search(&pos) {
+            Ok(k) => &segments[k],
+            Err(k) => &segments[k - 1],
+        };
+        match segment.binary_search(&pos) {
+            Ok(i) if i + 1 < segment.len() => {
+                ends.extend_from_slice(&segment[i + 1..]);
+                pos = segment[segment.len() - 1];
+            }
+            _ => {
+                pos = next_boundary(&mut chunker, buffer, pos);
+                ends.push(pos);
+            }
+        }
+    }
+
+    let mut start = 0;
+    ends.into_iter()
+        .map(|end| {
+            let slice = &buffer[start..end];
+            start = end;
+            slice
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use {
+        Chunker, ChunkerImpl, FastCDC, FixedSizeChunker, GearChunker, Polynomial, RabinHash,
+        TTTDChunker,
+    };
+
+    fn check<I: ChunkerImpl + Clone + Send>(chunker: I, data: &[u8]) {
+        let expected: Vec<_> = Chunker::new(chunker.clone()).slices(data).collect();
+        for &threads in &[0, 1, 2, 3, 7, 16] {
+            let result = Chunker::new(chunker.clone()).par_slices(data, threads);
+            assert_eq!(result, expected);
+        }
+    }
+
+    #[test]
+    fn test_par_slices() {
+        let mut data = vec![0u8; 1 << 18];
+        StdRng::seed_from_u64(12).fill(&mut data[..]);
+
+        check(GearChunker::new(0xfc00_0000), &data);
+        check(FastCDC::new(256, 1024, 4096), &data);
+        check(FixedSizeChunker::new(1000), &data);
+        check(
+            Chunker::new(GearChunker::new(0xffff_0000))
+                .max_size(1500)
+                .inner,
+            &data,
+        );
+        check(
+            Chunker::new(GearChunker::new(0xf000_0000))
+                .min_size(40)
+                .inner,
+            &data,
+        );
+        // Cuts at backup breakpoints depend on the data after the segment
+        let hash = RabinHash::new(Polynomial::generate(4), 16);
+        check(TTTDChunker::new(hash, 64, 1024, 1024, 128), &data);
+
+        // Fewer bytes than threads, and no data at all
+        check(GearChunker::new(0xf000_0000), &data[..10]);
+        check(GearChunker::new(0xf000_0000), &[]);
+    }
+}
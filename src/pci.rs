@@ -112,4 +112,8 @@ impl<const W: usize> ChunkerImpl for PCIChunker<W> {
     fn reset(&mut self) {
         self.state.reset()
     }
+
+    fn window_size(&self) -> Option<usize> {
+        Some(W)
+    }
 }
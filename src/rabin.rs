@@ -0,0 +1,474 @@
+use {ChunkerImpl, RollingHash};
+
+/// A polynomial over GF(2), stored as the bits of a `u64`.
+///
+/// Bit `i` is the coefficient of `x^i`, e.g. `Polynomial(0b1011)` is `x^3 + x + 1`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Polynomial(pub u64);
+
+impl Polynomial {
+    /// Returns the degree of the polynomial, `None` for the zero polynomial.
+    pub fn degree(self) -> Option<u32> {
+        if self.0 == 0 {
+            None
+        } else {
+            Some(63 - self.0.leading_zeros())
+        }
+    }
+
+    /// Returns the remainder of the division by `modulus`.
+    pub fn modulo(self, modulus: Polynomial) -> Polynomial {
+        let deg_m = modulus.degree().expect("division by zero polynomial");
+        let mut x = self.0;
+        while x != 0 && 63 - x.leading_zeros() >= deg_m {
+            x ^= modulus.0 << (63 - x.leading_zeros() - deg_m);
+        }
+        Polynomial(x)
+    }
+
+    /// Returns `self * other mod modulus`.
+    fn mulmod(self, other: Polynomial, modulus: Polynomial) -> Polynomial {
+        let mut a = self.modulo(modulus);
+        let mut b = other.0;
+        let mut res = 0;
+        while b != 0 {
+            if b & 1 != 0 {
+                res ^= a.0;
+            }
+            a = Polynomial(a.0 << 1).modulo(modulus);
+            b >>= 1;
+        }
+        Polynomial(res)
+    }
+
+    fn gcd(self, other: Polynomial) -> Polynomial {
+        let (mut a, mut b) = (self, other);
+        while b.0 != 0 {
+            let r = a.modulo(b);
+            a = b;
+            b = r;
+        }
+        a
+    }
+
+    /// Checks whether the polynomial is irreducible over GF(2).
+    ///
+    /// This uses Ben-Or's test: a polynomial `f` of degree `d` is irreducible if
+    /// `gcd(f, x^(2^i) - x mod f) = 1` for all `1 <= i <= d/2`.
+    ///
+    /// Only polynomials of degree up to 62 are supported.
+    pub fn is_irreducible(self) -> bool {
+        let degree = match self.degree() {
+            Some(d) => d,
+            None => return false,
+        };
+        assert!(degree < 63, "polynomial degree too high");
+        let x = Polynomial(0b10);
+        // x^(2^i) mod self, by repeated squaring
+        let mut power = x;
+        for _ in 0..degree / 2 {
+            power = power.mulmod(power, self);
+            if self.gcd(Polynomial(power.0 ^ x.0)).0 != 1 {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Derives an irreducible polynomial of degree 53 from the given seed.
+    ///
+    /// This deterministically tries pseudo-random candidates until one is irreducible, so the same
+    /// seed always gives the same polynomial. Use a random seed to get a random polynomial.
+    /// Degree 53 is the largest prime below 64 - 8, which is the largest degree usable by
+    /// `RabinChunker`.
+    pub fn generate(seed: u64) -> Polynomial {
+        let mut state = seed;
+        loop {
+            // splitmix64
+            state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+            let mut z = state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+            z ^= z >> 31;
+
+            // Set the highest bit for the degree to be 53, and the lowest bit so that the
+            // polynomial is not trivially divisible by x
+            let candidate = Polynomial(z & ((1 << 53) - 1) | 1 << 53 | 1);
+            if candidate.is_irreducible() {
+                return candidate;
+            }
+        }
+    }
+}
+
+/// Rolling Rabin fingerprint over a sliding window of bytes.
+///
+/// This keeps the fingerprint of the last `window_size` bytes, modulo the polynomial. Bytes are
+/// appended by shifting the fingerprint and reducing it using a precomputed table, and removed by
+/// XORing the precomputed fingerprint of the outgoing byte followed by `window_size - 1` zeros.
+///
+/// The polynomial needs to have a degree between 8 and 56.
+#[derive(Debug, Clone)]
+pub struct RabinHash {
+    /// The fingerprint of the current window.
+    digest: u64,
+    window: Vec<u8>,
+    wpos: usize,
+    shift: u32,
+    out_table: Box<[u64; 256]>,
+    mod_table: Box<[u64; 256]>,
+}
+
+impl RabinHash {
+    /// Creates the fingerprint of a window of zeros.
+    pub fn new(polynomial: Polynomial, window_size: usize) -> RabinHash {
+        let degree = polynomial.degree().unwrap_or(0);
+        assert!(
+            (8..=56).contains(&degree),
+            "polynomial degree needs to be between 8 and 56"
+        );
+        assert!(window_size > 0, "window_size needs to be at least 1");
+
+        let mut hash = RabinHash {
+            digest: 0,
+            window: vec![0; window_size],
+            wpos: 0,
+            shift: degree - 8,
+            out_table: Box::new([0; 256]),
+            mod_table: Box::new([0; 256]),
+        };
+
+        // mod_table[b] has the reduction of b * x^degree in its low bits, and b * x^degree itself
+        // in its high bits, so that the XOR clears the bits overflowing the degree
+        for b in 0..256 {
+            let overflow = (b as u64) << degree;
+            hash.mod_table[b] = Polynomial(overflow).modulo(polynomial).0 | overflow;
+        }
+
+        // out_table[b] is the fingerprint of b followed by window_size - 1 zeros
+        for b in 0..256 {
+            let mut h = RabinHash::append_byte(0, b as u8, polynomial);
+            for _ in 1..window_size {
+                h = RabinHash::append_byte(h, 0, polynomial);
+            }
+            hash.out_table[b] = h;
+        }
+
+        hash
+    }
+
+    fn append_byte(hash: u64, b: u8, polynomial: Polynomial) -> u64 {
+        Polynomial(hash << 8 | b as u64).modulo(polynomial).0
+    }
+}
+
+impl RollingHash for RabinHash {
+    fn window_size(&self) -> usize {
+        self.window.len()
+    }
+
+    fn slide(&mut self, b: u8) {
+        let out = self.window[self.wpos];
+        self.window[self.wpos] = b;
+        self.wpos += 1;
+        if self.wpos == self.window.len() {
+            self.wpos = 0;
+        }
+        self.digest ^= self.out_table[out as usize];
+
+        let index = (self.digest >> self.shift) as usize;
+        self.digest = (self.digest << 8 | b as u64) ^ self.mod_table[index];
+    }
+
+    fn digest(&self) -> u64 {
+        self.digest
+    }
+
+    fn reset(&mut self) {
+        self.digest = 0;
+        self.wpos = 0;
+        for b in self.window.iter_mut() {
+            *b = 0;
+        }
+    }
+}
+
+/// A chunker using a Rabin fingerprint over a sliding window, as in LBFS.
+///
+/// The fingerprint of the last `window_size` bytes is computed modulo an irreducible polynomial
+/// over GF(2) of degree at most 56, and a boundary is set after a byte if `fingerprint & mask ==
+/// target`. The average chunk size is 2^n for a mask with n bits set. No boundary is set before
+/// the window is full, so chunks are at least `window_size` bytes long.
+///
+/// Note that the fingerprint of a window of zeros is zero, so a non-zero `target` should be used to
+/// avoid chunking runs of zeros into minimum-size chunks.
+///
+/// The polynomial can be generated from a seed using `Polynomial::generate()`.
+///
+/// Source: Athicha Muthitacharoen, Benjie Chen, and David Mazières. "A low-bandwidth network file
+/// system." Proceedings of the eighteenth ACM symposium on Operating systems principles (SOSP '01).
+/// https://doi.org/10.1145/502034.502052
+///
+/// Michael O. Rabin. "Fingerprinting by random polynomials." Technical Report TR-15-81, Center for
+/// Research in Computing Technology, Harvard University, 1981.
+#[derive(Debug, Clone)]
+pub struct RabinChunker {
+    mask: u64,
+    target: u64,
+    hash: RabinHash,
+    pos: usize,
+}
+
+impl RabinChunker {
+    /// Creates a new Rabin chunker.
+    ///
+    /// `target` can't have bits set outside of `mask`.
+    pub fn new(polynomial: Polynomial, window_size: usize, mask: u64, target: u64) -> RabinChunker {
+        assert!(target & !mask == 0, "target has bits set outside of mask");
+        RabinChunker {
+            mask,
+            target,
+            hash: RabinHash::new(polynomial, window_size),
+            pos: 0,
+        }
+    }
+}
+
+impl ChunkerImpl for RabinChunker {
+    fn find_boundary(&mut self, data: &[u8]) -> Option<usize> {
+        let window_size = self.hash.window.len();
+        for (i, &b) in data.iter().enumerate() {
+            self.hash.slide(b);
+            self.pos += 1;
+
+            if self.pos >= window_size && self.hash.digest & self.mask == self.target {
+                return Some(i);
+            }
+        }
+
+        // No cut-point found within this block of data.
+        None
+    }
+
+    fn reset(&mut self) {
+        self.hash.reset();
+        self.pos = 0;
+    }
+
+    fn window_size(&self) -> Option<usize> {
+        Some(self.hash.window.len())
+    }
+}
+
+/// Window size used by restic.
+const RESTIC_WINDOW_SIZE: usize = 64;
+
+/// A chunker compatible with restic's, producing the same boundaries for the same polynomial.
+///
+/// This uses a Rabin fingerprint over a 64-byte window, with the per-repository polynomial found
+/// in the repository's config file (`chunker_polynomial`). Boundaries are set when the lowest 20
+/// bits of the fingerprint are zero, giving an average chunk size of 1 MiB, with chunks between
+/// 512 KiB and 8 MiB.
+///
+/// The first `min_size - 64` bytes of each chunk are not hashed, and the window starts with a
+/// single `1` byte, like in restic's implementation.
+///
+/// Source: https://github.com/restic/chunker
+#[derive(Debug, Clone)]
+pub struct ResticChunker {
+    min_size: usize,
+    max_size: usize,
+    split_mask: u64,
+    hash: RabinHash,
+    pos: usize,
+}
+
+impl ResticChunker {
+    /// Creates a chunker with restic's default sizes, 512 KiB to 8 MiB.
+    pub fn new(polynomial: Polynomial) -> ResticChunker {
+        ResticChunker::with_boundaries(polynomial, 512 * 1024, 8 * 1024 * 1024)
+    }
+
+    /// Creates a chunker with custom minimum and maximum chunk sizes.
+    ///
+    /// This is the same as restic's `NewWithBoundaries()`.
+    pub fn with_boundaries(
+        polynomial: Polynomial,
+        min_size: usize,
+        max_size: usize,
+    ) -> ResticChunker {
+        assert!(
+            min_size >= RESTIC_WINDOW_SIZE,
+            "min_size needs to be at least the window size"
+        );
+        assert!(
+            min_size <= max_size,
+            "min_size needs to be at most max_size"
+        );
+        let mut chunker = ResticChunker {
+            min_size,
+            max_size,
+            split_mask: (1 << 20) - 1,
+            hash: RabinHash::new(polynomial, RESTIC_WINDOW_SIZE),
+            pos: 0,
+        };
+        chunker.reset();
+        chunker
+    }
+}
+
+impl ChunkerImpl for ResticChunker {
+    fn find_boundary(&mut self, data: &[u8]) -> Option<usize> {
+        // Skip the bytes that can't influence the first fingerprint we check
+        let skip = (self.min_size - RESTIC_WINDOW_SIZE).saturating_sub(self.pos);
+        let start = ::std::cmp::min(skip, data.len());
+        self.pos += start;
+
+        for (i, &b) in data.iter().enumerate().skip(start) {
+            self.hash.slide(b);
+            self.pos += 1;
+
+            if self.pos >= self.min_size
+                && (self.hash.digest & self.split_mask == 0 || self.pos >= self.max_size)
+            {
+                return Some(i);
+            }
+        }
+
+        // No cut-point found within this block of data.
+        None
+    }
+
+    fn reset(&mut self) {
+        self.hash.reset();
+        self.hash.slide(1);
+        self.pos = 0;
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::{Polynomial, RabinChunker, RabinHash, ResticChunker};
+    use {Chunker, RollingHash};
+
+    #[test]
+    fn test_irreducible() {
+        assert!(Polynomial(0b111).is_irreducible()); // x^2 + x + 1
+        assert!(!Polynomial(0b101).is_irreducible()); // (x + 1)^2
+        assert!(Polynomial(0b1011).is_irreducible()); // x^3 + x + 1
+        assert!(!Polynomial(0b1111).is_irreducible()); // (x + 1)(x^2 + 1)
+        assert!(Polynomial(0x3DA3358B4DC173).is_irreducible());
+        assert!(!Polynomial(0x3DA3358B4DC173 << 1).is_irreducible());
+
+        let p = Polynomial::generate(42);
+        assert_eq!(p.degree(), Some(53));
+        assert!(p.is_irreducible());
+        assert_eq!(p, Polynomial::generate(42));
+        assert!(p != Polynomial::generate(43));
+    }
+
+    #[test]
+    fn test_rolling() {
+        // The rolling fingerprint is the same as the fingerprint of the window alone
+        let polynomial = Polynomial(0x3DA3358B4DC173);
+        let mut data = [0u8; 200];
+        StdRng::seed_from_u64(3).fill(&mut data[..]);
+
+        let mut rolling = RabinHash::new(polynomial, 16);
+        for (i, &b) in data.iter().enumerate() {
+            rolling.slide(b);
+            if i >= 16 {
+                let mut direct = RabinHash::new(polynomial, 16);
+                for &b in &data[i - 15..i + 1] {
+                    direct.slide(b);
+                }
+                assert_eq!(rolling.digest(), direct.digest());
+            }
+        }
+    }
+
+    #[test]
+    fn test_chunks() {
+        let mut data = vec![0u8; 1 << 20];
+        StdRng::seed_from_u64(4).fill(&mut data[..]);
+        let chunker = || RabinChunker::new(Polynomial::generate(1), 48, (1 << 12) - 1, 0x78);
+
+        let expected: Vec<_> = Chunker::new(chunker())
+            .slices(&data)
+            .map(|c| c.len())
+            .collect();
+        assert!(expected.iter().all(|&l| l >= 48));
+        let average = data.len() / expected.len();
+        assert!(3000 < average && average < 5000);
+
+        let result: Vec<_> = Chunker::new(chunker())
+            .chunks(&data[..])
+            .map(|c| c.unwrap().length())
+            .collect();
+        assert_eq!(result, expected);
+    }
+
+    /// Generates test data with xorshift64, so the fixture doesn't depend on `rand`.
+    pub(crate) fn generate(len: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed;
+        let mut data = Vec::with_capacity(len);
+        while data.len() < len {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            for i in 0..8 {
+                data.push((state >> (i * 8)) as u8);
+            }
+        }
+        data.truncate(len);
+        data
+    }
+
+    #[test]
+    fn test_restic() {
+        // Boundaries were obtained from rustic's restic-compatible chunker, checked against the
+        // test vectors of rustic_core; see tests/fixtures/README.md to regenerate them
+        let expected: Vec<usize> = include_str!("../tests/fixtures/restic.txt")
+            .lines()
+            .map(|l| l.parse().unwrap())
+            .collect();
+        let data = generate(16 * 1024 * 1024, 0x7265_7374_6963);
+        let chunker = || ResticChunker::new(Polynomial(0x3DA3358B4DC173));
+
+        let result: Vec<_> = Chunker::new(chunker())
+            .slices(&data)
+            .map(|c| c.len())
+            .collect();
+        assert_eq!(result, expected);
+
+        let result: Vec<_> = Chunker::new(chunker())
+            .chunks(&data[..])
+            .map(|c| c.unwrap().length())
+            .collect();
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_restic_zeros() {
+        // The fingerprint of zeros is zero, so each chunk has the minimum size
+        let data = vec![0u8; 5 * 512 * 1024 + 100];
+        let result: Vec<_> = Chunker::new(ResticChunker::new(Polynomial(0x3DA3358B4DC173)))
+            .slices(&data)
+            .map(|c| c.len())
+            .collect();
+        assert_eq!(
+            result,
+            vec![
+                512 * 1024,
+                512 * 1024,
+                512 * 1024,
+                512 * 1024,
+                512 * 1024,
+                100
+            ]
+        );
+    }
+}
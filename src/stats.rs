@@ -0,0 +1,291 @@
+use std::io::{self, Read};
+
+use {ChunkInfo, ChunkInfoStream, ChunkerImpl, StreamBuffer, BUF_SIZE};
+
+/// Number of linear sub-buckets in each power of two, for percentiles.
+const SUB_BUCKETS: usize = 16;
+const SUB_BUCKET_BITS: u32 = 4;
+
+/// Statistics on chunk sizes, collected without keeping every size.
+///
+/// Sizes are counted in buckets: sizes below 16 exactly, and larger ones in 16
+/// buckets per power of two, so that `percentile()` is within 1/16 (6.25%) of
+/// the actual size. This uses a few KiB of memory at most.
+#[derive(Debug, Default, Clone)]
+pub struct ChunkStats {
+    count: u64,
+    total: u64,
+    min: usize,
+    max: usize,
+    mean: f64,
+    m2: f64, // Sum of squared differences from the mean, see Welford's algorithm
+    buckets: Vec<u64>,
+}
+
+impl ChunkStats {
+    pub fn new() -> ChunkStats {
+        Default::default()
+    }
+
+    /// Adds a chunk of the given size.
+    pub fn add(&mut self, length: usize) {
+        if self.count == 0 || length < self.min {
+            self.min = length;
+        }
+        if self.count == 0 || length > self.max {
+            self.max = length;
+        }
+        self.count += 1;
+        self.total += length as u64;
+        let delta = length as f64 - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (length as f64 - self.mean);
+
+        let bucket = bucket(length);
+        if self.buckets.len() <= bucket {
+            self.buckets.resize(bucket + 1, 0);
+        }
+        self.buckets[bucket] += 1;
+    }
+
+    /// The number of chunks.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// The sum of the chunk sizes.
+    pub fn total_length(&self) -> u64 {
+        self.total
+    }
+
+    /// The smallest size, or `None` if there are no chunks.
+    pub fn min(&self) -> Option<usize> {
+        if self.count == 0 {
+            None
+        } else {
+            Some(self.min)
+        }
+    }
+
+    /// The largest size, or `None` if there are no chunks.
+    pub fn max(&self) -> Option<usize> {
+        if self.count == 0 {
+            None
+        } else {
+            Some(self.max)
+        }
+    }
+
+    /// The average size, 0 if there are no chunks.
+    pub fn mean(&self) -> f64 {
+        self.mean
+    }
+
+    /// The standard deviation of the sizes.
+    pub fn stddev(&self) -> f64 {
+        if self.count == 0 {
+            0.0
+        } else {
+            (self.m2 / self.count as f64).sqrt()
+        }
+    }
+
+    /// An approximation of the size below which `p` percent of the chunks are.
+    ///
+    /// This is the upper bound of the bucket holding that chunk, so it is never
+    /// below the exact value and at most 1/16 above it. `percentile(50.0)` is
+    /// the median. Returns `None` if there are no chunks.
+    pub fn percentile(&self, p: f64) -> Option<usize> {
+        assert!(
+            (0.0..=100.0).contains(&p),
+            "percentile needs to be within 0..=100"
+        );
+        if self.count == 0 {
+            return None;
+        }
+        let rank = ((p / 100.0 * self.count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (i, &n) in self.buckets.iter().enumerate() {
+            seen += n;
+            if seen >= rank {
+                return Some(bucket_high(i).max(self.min).min(self.max));
+            }
+        }
+        unreachable!()
+    }
+
+    /// The number of chunks by size: element `i` is the number of chunks of
+    /// size `2^i` to `2^(i+1) - 1`. Empty chunks are counted in the first one.
+    pub fn histogram(&self) -> Vec<u64> {
+        let mut histogram = Vec::new();
+        for (i, &n) in self.buckets.iter().enumerate() {
+            let log2 = log2(bucket_low(i));
+            if histogram.len() <= log2 {
+                histogram.resize(log2 + 1, 0);
+            }
+            histogram[log2] += n;
+        }
+        histogram
+    }
+}
+
+/// The integer base-2 logarithm, 0 for 0.
+fn log2(n: usize) -> usize {
+    if n == 0 {
+        0
+    } else {
+        (0usize.leading_zeros() - 1 - n.leading_zeros()) as usize
+    }
+}
+
+/// The bucket a size is counted in.
+fn bucket(length: usize) -> usize {
+    if length < SUB_BUCKETS {
+        length
+    } else {
+        let shift = log2(length) - SUB_BUCKET_BITS as usize;
+        let sub = (length >> shift) - SUB_BUCKETS;
+        SUB_BUCKETS * (shift + 1) + sub
+    }
+}
+
+/// The smallest size in a bucket.
+fn bucket_low(bucket: usize) -> usize {
+    if bucket < SUB_BUCKETS {
+        bucket
+    } else {
+        let shift = bucket / SUB_BUCKETS - 1;
+        (SUB_BUCKETS + bucket % SUB_BUCKETS) << shift
+    }
+}
+
+/// The largest size in a bucket.
+fn bucket_high(bucket: usize) -> usize {
+    if bucket < SUB_BUCKETS {
+        bucket
+    } else {
+        bucket_low(bucket) + ((1 << (bucket / SUB_BUCKETS - 1)) - 1)
+    }
+}
+
+/// Iterator on the positions of chunks, that also collects their statistics.
+///
+/// This is created by `ChunkInfoStream::with_stats()`.
+pub struct ChunkStatsStream<R: Read, I: ChunkerImpl, B: StreamBuffer = [u8; BUF_SIZE]> {
+    inner: ChunkInfoStream<R, I, B>,
+    stats: ChunkStats,
+}
+
+impl<R: Read, I: ChunkerImpl, B: StreamBuffer> ChunkStatsStream<R, I, B> {
+    pub(crate) fn new(inner: ChunkInfoStream<R, I, B>) -> ChunkStatsStream<R, I, B> {
+        ChunkStatsStream {
+            inner,
+            stats: ChunkStats::new(),
+        }
+    }
+
+    /// The statistics on the chunks returned so far.
+    pub fn stats(&self) -> &ChunkStats {
+        &self.stats
+    }
+
+    pub fn into_stats(self) -> ChunkStats {
+        self.stats
+    }
+}
+
+impl<R: Read, I: ChunkerImpl, B: StreamBuffer> Iterator for ChunkStatsStream<R, I, B> {
+    type Item = io::Result<ChunkInfo>;
+
+    fn next(&mut self) -> Option<io::Result<ChunkInfo>> {
+        let chunk = self.inner.next();
+        if let Some(Ok(ref info)) = chunk {
+            self.stats.add(info.length());
+        }
+        chunk
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::{bucket, bucket_high, bucket_low, ChunkStats};
+    use {Chunker, FastCDC, ZPAQ};
+
+    #[test]
+    fn test_buckets() {
+        for length in (0..5000).chain(vec![usize::MAX - 1, usize::MAX]) {
+            let b = bucket(length);
+            assert!(bucket_low(b) <= length && length <= bucket_high(b));
+            if b > 0 {
+                assert_eq!(bucket_high(b - 1) + 1, bucket_low(b));
+            }
+        }
+        assert_eq!((bucket_low(16), bucket_high(16)), (16, 16));
+        assert_eq!((bucket_low(32), bucket_high(32)), (32, 33));
+    }
+
+    #[test]
+    fn test_stats() {
+        let mut stats = ChunkStats::new();
+        assert_eq!(stats.min(), None);
+        assert_eq!(stats.percentile(50.0), None);
+        assert!(stats.histogram().is_empty());
+
+        for &length in &[2, 4, 4, 4, 5, 5, 7, 9] {
+            stats.add(length);
+        }
+        assert_eq!(stats.count(), 8);
+        assert_eq!(stats.total_length(), 40);
+        assert_eq!((stats.min(), stats.max()), (Some(2), Some(9)));
+        assert_eq!(stats.mean(), 5.0);
+        assert_eq!(stats.stddev(), 2.0);
+        assert_eq!(stats.percentile(0.0), Some(2));
+        assert_eq!(stats.percentile(50.0), Some(4));
+        assert_eq!(stats.percentile(100.0), Some(9));
+        assert_eq!(stats.histogram(), vec![0, 1, 6, 1]);
+
+        // Approximate percentiles
+        let mut stats = ChunkStats::new();
+        for length in 1000..2000 {
+            stats.add(length);
+        }
+        let median = stats.percentile(50.0).unwrap();
+        assert!((1499..=1499 + 1499 / 16).contains(&median));
+        assert_eq!(stats.percentile(100.0), Some(1999));
+        assert_eq!(stats.histogram()[9..], [24, 976]);
+    }
+
+    #[test]
+    fn test_stats_stream() {
+        let mut data = vec![0u8; 1 << 16];
+        StdRng::seed_from_u64(18).fill(&mut data[..]);
+        let chunker = || Chunker::new(FastCDC::new(256, 1024, 4096));
+
+        let mut chunks = chunker().chunks(&data[..]).with_stats();
+        let sizes: Vec<_> = chunks.by_ref().map(|c| c.unwrap().length()).collect();
+        let stats = chunks.into_stats();
+        assert_eq!(stats.count(), sizes.len() as u64);
+        assert_eq!(stats.total_length(), data.len() as u64);
+        assert_eq!(stats.min(), sizes.iter().cloned().min());
+        assert_eq!(stats.max(), sizes.iter().cloned().max());
+    }
+
+    #[test]
+    fn test_stats_mean() {
+        // ZPAQ with 8 bits gives chunks of about 256 bytes
+        let mut data = vec![0u8; 1 << 20];
+        StdRng::seed_from_u64(22).fill(&mut data[..]);
+
+        let mut chunks = Chunker::new(ZPAQ::new(8)).chunks(&data[..]).with_stats();
+        for chunk in chunks.by_ref() {
+            chunk.unwrap();
+        }
+        let stats = chunks.into_stats();
+        assert!(240.0 <= stats.mean() && stats.mean() <= 270.0);
+        let mean = stats.total_length() as f64 / stats.count() as f64;
+        assert!((stats.mean() - mean).abs() < 1e-6);
+    }
+}
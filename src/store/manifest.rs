@@ -0,0 +1,307 @@
+use std::io::{self, BufRead, Read, Write};
+use std::iter::FromIterator;
+
+use super::{from_hex, to_hex, ChunkStore};
+use varint::{read_varint, write_varint};
+use {ChunkHasher, ChunkInfo};
+
+/// Magic bytes at the start of the binary encoding.
+const BINARY_MAGIC: &[u8; 4] = b"CDCM";
+
+/// First line of the text encoding, followed by the version.
+const TEXT_HEADER: &str = "cdchunking-manifest";
+
+/// Current version of both encodings.
+const VERSION: u8 = 1;
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// A chunk in a `Manifest`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestEntry {
+    digest: Vec<u8>,
+    length: u64,
+}
+
+impl ManifestEntry {
+    pub fn digest(&self) -> &[u8] {
+        &self.digest
+    }
+
+    pub fn length(&self) -> u64 {
+        self.length
+    }
+}
+
+/// The ordered list of the chunks making up a file.
+///
+/// A manifest can be collected from `Chunker::hashed_chunks()`, and saved in a
+/// compact binary encoding or a human-readable text encoding. Both start with a
+/// version number. All the digests in a manifest need to have the same size to
+/// be encoded.
+///
+/// The binary encoding is the magic `CDCM`, the version byte, the size of the
+/// digests as one byte, the number of chunks, then the size and digest of each
+/// chunk. Numbers are encoded as LEB128 variable-length integers.
+///
+/// The text encoding is a `cdchunking-manifest 1` line, followed by a line for
+/// each chunk with the digest in hexadecimal and the size in decimal, separated
+/// by a space.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Manifest {
+    entries: Vec<ManifestEntry>,
+}
+
+impl Manifest {
+    pub fn new() -> Manifest {
+        Default::default()
+    }
+
+    /// Adds a chunk at the end of the file.
+    pub fn push(&mut self, digest: &[u8], length: u64) {
+        self.entries.push(ManifestEntry {
+            digest: digest.to_owned(),
+            length,
+        });
+    }
+
+    pub fn entries(&self) -> &[ManifestEntry] {
+        &self.entries
+    }
+
+    /// The number of chunks.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The size of the file, i.e. the sum of the sizes of the chunks.
+    pub fn total_length(&self) -> u64 {
+        self.entries.iter().map(|e| e.length).sum()
+    }
+
+    /// The size of the digests, checking that they all have the same.
+    fn digest_size(&self) -> io::Result<usize> {
+        let size = self.entries.first().map_or(0, |e| e.digest.len());
+        if size > 255 || self.entries.iter().any(|e| e.digest.len() != size) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "digests need to be of the same size, at most 255 bytes",
+            ));
+        }
+        Ok(size)
+    }
+
+    /// Writes the binary encoding of the manifest.
+    pub fn write_binary<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let digest_size = self.digest_size()?;
+        writer.write_all(BINARY_MAGIC)?;
+        writer.write_all(&[VERSION, digest_size as u8])?;
+        write_varint(&mut writer, self.entries.len() as u64)?;
+        for entry in &self.entries {
+            write_varint(&mut writer, entry.length)?;
+            writer.write_all(&entry.digest)?;
+        }
+        Ok(())
+    }
+
+    /// Reads a manifest from its binary encoding.
+    pub fn read_binary<R: Read>(mut reader: R) -> io::Result<Manifest> {
+        let mut header = [0u8; 6];
+        reader.read_exact(&mut header)?;
+        if &header[..4] != BINARY_MAGIC {
+            return Err(invalid_data("not a manifest"));
+        }
+        if header[4] != VERSION {
+            return Err(invalid_data("unsupported manifest version"));
+        }
+        let digest_size = header[5] as usize;
+        let count = read_varint(&mut reader)?;
+        let mut manifest = Manifest::new();
+        for _ in 0..count {
+            let length = read_varint(&mut reader)?;
+            let mut digest = vec![0u8; digest_size];
+            reader.read_exact(&mut digest)?;
+            manifest.entries.push(ManifestEntry { digest, length });
+        }
+        Ok(manifest)
+    }
+
+    /// Writes the text encoding of the manifest.
+    pub fn write_text<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        self.digest_size()?;
+        writeln!(writer, "{} {}", TEXT_HEADER, VERSION)?;
+        for entry in &self.entries {
+            writeln!(writer, "{} {}", to_hex(&entry.digest), entry.length)?;
+        }
+        Ok(())
+    }
+
+    /// Reads a manifest from its text encoding.
+    pub fn read_text<R: BufRead>(reader: R) -> io::Result<Manifest> {
+        let mut lines = reader.lines();
+        match lines.next() {
+            Some(line) => {
+                let line = line?;
+                let mut header = line.split(' ');
+                if header.next() != Some(TEXT_HEADER) {
+                    return Err(invalid_data("not a manifest"));
+                }
+                if header.next() != Some(&VERSION.to_string()) || header.next().is_some() {
+                    return Err(invalid_data("unsupported manifest version"));
+                }
+            }
+            None => return Err(invalid_data("not a manifest")),
+        }
+        let mut manifest = Manifest::new();
+        for line in lines {
+            let line = line?;
+            let mut fields = line.split(' ');
+            let entry = match (fields.next(), fields.next(), fields.next()) {
+                (Some(digest), Some(length), None) => from_hex(digest).and_then(|digest| {
+                    length
+                        .parse()
+                        .ok()
+                        .map(|length| ManifestEntry { digest, length })
+                }),
+                _ => None,
+            };
+            match entry {
+                Some(entry) => manifest.entries.push(entry),
+                None => return Err(invalid_data("invalid manifest line")),
+            }
+        }
+        manifest
+            .digest_size()
+            .map_err(|_| invalid_data("digests of different sizes"))?;
+        Ok(manifest)
+    }
+
+    /// Writes the original file from the chunks in the store.
+    ///
+    /// The digest and size of each chunk is checked using `hasher`, which
+    /// needs to be the one that was used to build the manifest, so the number
+    /// of bytes written, which is returned, is always `total_length()`. Note
+    /// that if an error is returned, part of the file might have been written.
+    pub fn reconstruct<S, H, W>(&self, store: &S, mut hasher: H, mut writer: W) -> io::Result<u64>
+    where
+        S: ChunkStore + ?Sized,
+        H: ChunkHasher,
+        W: Write,
+    {
+        let mut total = 0;
+        for entry in &self.entries {
+            let chunk = match store.get(&entry.digest)? {
+                Some(chunk) => chunk,
+                None => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!("chunk {} is not in the store", to_hex(&entry.digest)),
+                    ))
+                }
+            };
+            if chunk.len() as u64 != entry.length
+                || hasher.digest(&chunk).as_ref() != &entry.digest[..]
+            {
+                return Err(invalid_data(&format!(
+                    "chunk {} is corrupted",
+                    to_hex(&entry.digest)
+                )));
+            }
+            writer.write_all(&chunk)?;
+            total += entry.length;
+        }
+        Ok(total)
+    }
+}
+
+impl<D: AsRef<[u8]>> FromIterator<(ChunkInfo, D)> for Manifest {
+    fn from_iter<T: IntoIterator<Item = (ChunkInfo, D)>>(iter: T) -> Manifest {
+        let mut manifest = Manifest::new();
+        for (info, digest) in iter {
+            manifest.push(digest.as_ref(), info.length() as u64);
+        }
+        manifest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::io;
+
+    use super::Manifest;
+    use hash::tests::Fnv;
+    use store::{store_reader, ChunkStore, MemoryChunkStore};
+    use {Chunker, FastCDC};
+
+    #[test]
+    fn test_encodings() {
+        let mut data = vec![0u8; 1 << 16];
+        StdRng::seed_from_u64(14).fill(&mut data[..]);
+        let manifest: Manifest = Chunker::new(FastCDC::new(256, 1024, 4096))
+            .hashed_chunks(&data[..], Fnv::new())
+            .collect::<io::Result<_>>()
+            .unwrap();
+        assert_eq!(manifest.total_length(), data.len() as u64);
+
+        let mut binary = Vec::new();
+        manifest.write_binary(&mut binary).unwrap();
+        assert_eq!(&binary[..6], b"CDCM\x01\x08");
+        assert_eq!(Manifest::read_binary(&binary[..]).unwrap(), manifest);
+        assert!(Manifest::read_binary(&binary[..binary.len() - 1]).is_err());
+
+        let mut text = Vec::new();
+        manifest.write_text(&mut text).unwrap();
+        assert!(text.starts_with(b"cdchunking-manifest 1\n"));
+        assert_eq!(Manifest::read_text(&text[..]).unwrap(), manifest);
+        assert!(Manifest::read_text(&b"cdchunking-manifest 2\n"[..]).is_err());
+        assert!(Manifest::read_text(&b"cdchunking-manifest 1\nabc 12\n"[..]).is_err());
+
+        // Digests of different sizes can't be encoded
+        let mut mixed = Manifest::new();
+        mixed.push(b"ab", 1);
+        mixed.push(b"abc", 1);
+        assert!(mixed.write_binary(io::sink()).is_err());
+        assert!(mixed.write_text(io::sink()).is_err());
+    }
+
+    #[test]
+    fn test_reconstruct() {
+        let mut data = vec![0u8; 1 << 16];
+        StdRng::seed_from_u64(15).fill(&mut data[..]);
+        let chunker = Chunker::new(FastCDC::new(256, 1024, 4096));
+        let mut store = MemoryChunkStore::new();
+        let (manifest, _) = store_reader(chunker, &data[..], Fnv::new(), &mut store).unwrap();
+
+        let mut result = Vec::new();
+        let written = manifest
+            .reconstruct(&store, Fnv::new(), &mut result)
+            .unwrap();
+        assert_eq!(written, data.len() as u64);
+        assert_eq!(result, data);
+
+        // Missing chunk
+        let err = manifest
+            .reconstruct(&MemoryChunkStore::new(), Fnv::new(), io::sink())
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+
+        // Corrupted chunk
+        let mut corrupted = MemoryChunkStore::new();
+        let first = &manifest.entries()[0];
+        corrupted
+            .put(first.digest(), &vec![0u8; first.length() as usize])
+            .unwrap();
+        let err = manifest
+            .reconstruct(&corrupted, Fnv::new(), io::sink())
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
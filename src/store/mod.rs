@@ -0,0 +1,258 @@
+//! Content-addressed storage of chunks.
+//!
+//! A `ChunkStore` holds chunks indexed by their digest, so that a chunk
+//! appearing multiple times is only stored once. The `store_reader()` function
+//! chunks a file into a store, returning the `Manifest` needed to get the file
+//! back with `Manifest::reconstruct()`.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use {ChunkHasher, Chunker, ChunkerImpl};
+
+mod manifest;
+
+pub use self::manifest::{Manifest, ManifestEntry};
+
+/// Storage for chunks, indexed by digest.
+pub trait ChunkStore {
+    /// Stores a chunk, doing nothing if it is already present.
+    fn put(&mut self, digest: &[u8], data: &[u8]) -> io::Result<()>;
+
+    /// Gets a chunk, or `None` if it is not in the store.
+    fn get(&self, digest: &[u8]) -> io::Result<Option<Vec<u8>>>;
+
+    /// Checks whether a chunk is in the store.
+    fn contains(&self, digest: &[u8]) -> io::Result<bool>;
+}
+
+/// A `ChunkStore` in memory, mostly useful for tests.
+#[derive(Debug, Default, Clone)]
+pub struct MemoryChunkStore {
+    chunks: HashMap<Vec<u8>, Vec<u8>>,
+}
+
+impl MemoryChunkStore {
+    pub fn new() -> MemoryChunkStore {
+        Default::default()
+    }
+
+    /// The number of chunks in the store.
+    pub fn len(&self) -> usize {
+        self.chunks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+}
+
+impl ChunkStore for MemoryChunkStore {
+    fn put(&mut self, digest: &[u8], data: &[u8]) -> io::Result<()> {
+        self.chunks
+            .entry(digest.to_owned())
+            .or_insert_with(|| data.to_owned());
+        Ok(())
+    }
+
+    fn get(&self, digest: &[u8]) -> io::Result<Option<Vec<u8>>> {
+        Ok(self.chunks.get(digest).cloned())
+    }
+
+    fn contains(&self, digest: &[u8]) -> io::Result<bool> {
+        Ok(self.chunks.contains_key(digest))
+    }
+}
+
+/// A `ChunkStore` keeping each chunk in a file.
+///
+/// Files are named after the digest in hexadecimal, and sharded in directories
+/// named after its first byte, e.g. `ab/cdef0123...`. Chunks are written to a
+/// temporary file first, then renamed, so that a chunk file is never partial.
+#[derive(Debug, Clone)]
+pub struct FsChunkStore {
+    root: PathBuf,
+}
+
+impl FsChunkStore {
+    /// Opens the store in the given directory, which is created if needed.
+    pub fn new<P: AsRef<Path>>(root: P) -> io::Result<FsChunkStore> {
+        fs::create_dir_all(root.as_ref())?;
+        Ok(FsChunkStore {
+            root: root.as_ref().to_owned(),
+        })
+    }
+
+    /// Returns the path of the file holding a chunk.
+    pub fn chunk_path(&self, digest: &[u8]) -> io::Result<PathBuf> {
+        if digest.len() < 2 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "digest needs to be at least 2 bytes",
+            ));
+        }
+        let hex = to_hex(digest);
+        Ok(self.root.join(&hex[..2]).join(&hex[2..]))
+    }
+}
+
+impl ChunkStore for FsChunkStore {
+    fn put(&mut self, digest: &[u8], data: &[u8]) -> io::Result<()> {
+        let path = self.chunk_path(digest)?;
+        if path.exists() {
+            return Ok(());
+        }
+        fs::create_dir_all(path.parent().unwrap())?;
+        let temp = path.with_extension("tmp");
+        {
+            let mut file = File::create(&temp)?;
+            file.write_all(data)?;
+            file.sync_all()?;
+        }
+        fs::rename(&temp, &path)
+    }
+
+    fn get(&self, digest: &[u8]) -> io::Result<Option<Vec<u8>>> {
+        let mut file = match File::open(self.chunk_path(digest)?) {
+            Ok(f) => f,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)?;
+        Ok(Some(data))
+    }
+
+    fn contains(&self, digest: &[u8]) -> io::Result<bool> {
+        Ok(self.chunk_path(digest)?.exists())
+    }
+}
+
+pub(crate) fn to_hex(data: &[u8]) -> String {
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+    let mut hex = String::with_capacity(data.len() * 2);
+    for &b in data {
+        hex.push(DIGITS[(b >> 4) as usize] as char);
+        hex.push(DIGITS[(b & 0xf) as usize] as char);
+    }
+    hex
+}
+
+pub(crate) fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    let digit = |c: u8| (c as char).to_digit(16).map(|d| d as u8);
+    hex.as_bytes()
+        .chunks(2)
+        .map(|pair| match (digit(pair[0]), digit(pair[1])) {
+            (Some(high), Some(low)) => Some(high << 4 | low),
+            _ => None,
+        })
+        .collect()
+}
+
+/// How much data `store_reader()` added to the store.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct StoreStats {
+    /// Number of chunks that were not in the store yet.
+    pub new_chunks: usize,
+    /// Size of the chunks that were not in the store yet.
+    pub new_bytes: u64,
+    /// Number of chunks that were already in the store, or earlier in the file.
+    pub dedup_chunks: usize,
+    /// Size of the chunks that were already in the store, or earlier in the file.
+    pub dedup_bytes: u64,
+}
+
+/// Chunks a file into a store.
+///
+/// Each chunk is hashed with `hasher`, and put in the store if it is not in it
+/// already. The returned `Manifest` lists the digests of the chunks, allowing
+/// to reconstruct the file.
+pub fn store_reader<I, R, H, S>(
+    chunker: Chunker<I>,
+    reader: R,
+    mut hasher: H,
+    store: &mut S,
+) -> io::Result<(Manifest, StoreStats)>
+where
+    I: ChunkerImpl,
+    R: Read,
+    H: ChunkHasher,
+    S: ChunkStore + ?Sized,
+{
+    let mut manifest = Manifest::new();
+    let mut stats = StoreStats::default();
+    for chunk in chunker.whole_chunks(reader) {
+        let chunk = chunk?;
+        let digest = hasher.digest(&chunk);
+        let digest = digest.as_ref();
+        if store.contains(digest)? {
+            stats.dedup_chunks += 1;
+            stats.dedup_bytes += chunk.len() as u64;
+        } else {
+            store.put(digest, &chunk)?;
+            stats.new_chunks += 1;
+            stats.new_bytes += chunk.len() as u64;
+        }
+        manifest.push(digest, chunk.len() as u64);
+    }
+    Ok((manifest, stats))
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    use super::{store_reader, ChunkStore, FsChunkStore, MemoryChunkStore};
+    use hash::tests::Fnv;
+    use {Chunker, FastCDC};
+
+    fn check_store<S: ChunkStore>(store: &mut S) {
+        let mut data = vec![0u8; 1 << 16];
+        StdRng::seed_from_u64(13).fill(&mut data[..]);
+        let chunker = || Chunker::new(FastCDC::new(256, 1024, 4096));
+
+        let (manifest, stats) = store_reader(chunker(), &data[..], Fnv::new(), store).unwrap();
+        assert_eq!(stats.new_bytes, data.len() as u64);
+        assert_eq!(stats.dedup_bytes, 0);
+        assert_eq!(stats.new_chunks, manifest.len());
+
+        // Storing the same data again, with a modified start
+        data[0] ^= 1;
+        let (manifest, stats) = store_reader(chunker(), &data[..], Fnv::new(), store).unwrap();
+        assert_eq!(stats.new_chunks, 1);
+        assert_eq!(stats.new_bytes, manifest.entries()[0].length());
+        assert_eq!(stats.dedup_chunks, manifest.len() - 1);
+
+        for (entry, chunk) in manifest.entries().iter().zip(chunker().slices(&data)) {
+            assert!(store.contains(entry.digest()).unwrap());
+            assert_eq!(store.get(entry.digest()).unwrap().unwrap(), chunk);
+        }
+        assert_eq!(store.get(b"missing").unwrap(), None);
+    }
+
+    #[test]
+    fn test_memory_store() {
+        let mut store = MemoryChunkStore::new();
+        check_store(&mut store);
+    }
+
+    #[test]
+    fn test_fs_store() {
+        let root = env::temp_dir().join(format!("cdchunking-store-{}", process::id()));
+        let mut store = FsChunkStore::new(&root).unwrap();
+        check_store(&mut store);
+
+        let path = store.chunk_path(&[0xab, 0xcd, 0xef]).unwrap();
+        assert_eq!(path, root.join("ab").join("cdef"));
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,618 @@
+//! Measuring how chunkers handle edits to the data.
+//!
+//! The point of content-defined chunking is that inserting or removing bytes
+//! only changes the chunks around the edit: further on, the chunker finds the
+//! same boundaries as before, shifted by the size difference. Chunking at fixed
+//! offsets doesn't have that property, which is known as the boundary-shift
+//! problem.
+//!
+//! `boundary_shift()` chunks some data before and after an `Edit`, and reports
+//! how many boundaries survived and how far after the edit the chunker found
+//! the original boundaries again.
+//!
+//! `check_conformance()` checks that a `ChunkerImpl` finds the same boundaries
+//! however the data is split between calls to `find_boundary()`, which is easy
+//! to get wrong when writing a new one.
+//!
+//! This module is available with the `testing` feature.
+
+use std::cmp::min;
+use std::fmt;
+use std::io::{self, Read};
+
+use {ChunkInput, Chunker, ChunkerImpl};
+
+/// A change to some data, at a given offset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edit {
+    /// Inserts `length` random bytes before `offset`.
+    Insert { offset: usize, length: usize },
+    /// Removes `length` bytes from `offset`.
+    Delete { offset: usize, length: usize },
+    /// Replaces `length` bytes from `offset` with random bytes.
+    Overwrite { offset: usize, length: usize },
+}
+
+impl Edit {
+    fn offset(&self) -> usize {
+        match *self {
+            Edit::Insert { offset, .. }
+            | Edit::Delete { offset, .. }
+            | Edit::Overwrite { offset, .. } => offset,
+        }
+    }
+
+    /// The end of the edited range in the original data.
+    fn old_end(&self) -> usize {
+        match *self {
+            Edit::Insert { offset, .. } => offset,
+            Edit::Delete { offset, length } | Edit::Overwrite { offset, length } => offset + length,
+        }
+    }
+
+    /// The end of the edited range in the modified data.
+    fn new_end(&self) -> usize {
+        match *self {
+            Edit::Delete { offset, .. } => offset,
+            Edit::Insert { offset, length } | Edit::Overwrite { offset, length } => offset + length,
+        }
+    }
+
+    /// Returns the modified data, using `seed` to generate new bytes.
+    ///
+    /// Panics if the edit is not within the data.
+    pub fn apply(&self, data: &[u8], seed: u64) -> Vec<u8> {
+        assert!(self.old_end() <= data.len(), "edit is out of the data");
+        let new_length = self.new_end() - self.offset();
+        let mut result = Vec::with_capacity(data.len() + new_length);
+        result.extend_from_slice(&data[..self.offset()]);
+        match *self {
+            Edit::Delete { .. } => {}
+            Edit::Insert { .. } | Edit::Overwrite { .. } => {
+                result.extend(random_data(new_length, seed))
+            }
+        }
+        result.extend_from_slice(&data[self.old_end()..]);
+        result
+    }
+}
+
+/// SplitMix64 pseudo-random number generator.
+///
+/// Source: Guy L. Steele, Doug Lea, and Christine H. Flood. "Fast splittable
+/// pseudorandom number generators." OOPSLA 2014.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+/// Generates pseudo-random data, the same for a given seed.
+pub fn random_data(length: usize, seed: u64) -> Vec<u8> {
+    let mut rng = SplitMix64(seed);
+    let mut data = Vec::with_capacity(length + 8);
+    while data.len() < length {
+        let z = rng.next();
+        for i in 0..8 {
+            data.push((z >> (i * 8)) as u8);
+        }
+    }
+    data.truncate(length);
+    data
+}
+
+/// The result of `boundary_shift()`.
+///
+/// Boundaries are the offsets between chunks, not counting the end of the data.
+/// Boundaries inside the edited range are not counted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShiftReport {
+    /// Number of boundaries of the original data before the edit.
+    pub boundaries_before: usize,
+    /// How many of them are also boundaries of the modified data.
+    pub preserved_before: usize,
+    /// Number of boundaries of the original data after the edit.
+    pub boundaries_after: usize,
+    /// How many of them are also boundaries of the modified data, at the same
+    /// distance from the edit.
+    pub preserved_after: usize,
+    /// The distance from the end of the edit to the first preserved boundary
+    /// after it, or `None` if no boundary after the edit was preserved.
+    pub resync_distance: Option<usize>,
+}
+
+impl ShiftReport {
+    /// The proportion of boundaries after the edit that were preserved, 1.0 if
+    /// there were none.
+    pub fn preserved_ratio_after(&self) -> f64 {
+        if self.boundaries_after == 0 {
+            1.0
+        } else {
+            self.preserved_after as f64 / self.boundaries_after as f64
+        }
+    }
+}
+
+fn boundaries<I: ChunkerImpl>(chunker: I, data: &[u8]) -> Vec<usize> {
+    let mut pos = 0;
+    let mut result: Vec<usize> = Chunker::new(chunker)
+        .slices(data)
+        .map(|c| {
+            pos += c.len();
+            pos
+        })
+        .collect();
+    result.pop();
+    result
+}
+
+/// Chunks data before and after an edit, and compares the boundaries.
+///
+/// `new_chunker` is called to get a fresh chunker for each version of the data,
+/// and `seed` is used to generate new bytes, see `Edit::apply()`.
+pub fn boundary_shift<I, F>(mut new_chunker: F, data: &[u8], edit: &Edit, seed: u64) -> ShiftReport
+where
+    I: ChunkerImpl,
+    F: FnMut() -> I,
+{
+    let modified = edit.apply(data, seed);
+    let old = boundaries(new_chunker(), data);
+    let new = boundaries(new_chunker(), &modified);
+    let (offset, old_end, new_end) = (edit.offset(), edit.old_end(), edit.new_end());
+
+    let mut report = ShiftReport {
+        boundaries_before: 0,
+        preserved_before: 0,
+        boundaries_after: 0,
+        preserved_after: 0,
+        resync_distance: None,
+    };
+    for &pos in &old {
+        if pos <= offset {
+            report.boundaries_before += 1;
+            if new.binary_search(&pos).is_ok() {
+                report.preserved_before += 1;
+            }
+        } else if pos >= old_end {
+            report.boundaries_after += 1;
+            let shifted = pos - old_end + new_end;
+            if new.binary_search(&shifted).is_ok() {
+                report.preserved_after += 1;
+                if report.resync_distance.is_none() {
+                    report.resync_distance = Some(shifted - new_end);
+                }
+            }
+        }
+    }
+    report
+}
+
+/// Sizes of the reads used by `check_conformance()`.
+const READ_SIZES: &[usize] = &[1, 2, 3, 5, 7, 13, 31, 127, 509, 4093];
+
+/// Largest read size when reading random amounts.
+const MAX_RANDOM_READ: usize = 1024;
+
+/// How `check_conformance()` feeds the data to the chunker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    /// `Chunker::stream()`, over a reader returning that many bytes at a time.
+    Stream(usize),
+    /// `Chunker::stream()`, over a reader returning random amounts.
+    StreamRandom,
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Method::Stream(1) => write!(f, "stream() with reads of 1 byte"),
+            Method::Stream(size) => write!(f, "stream() with reads of {} bytes", size),
+            Method::StreamRandom => write!(f, "stream() with reads of random sizes"),
+        }
+    }
+}
+
+/// A difference found by `check_conformance()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// How the chunks were computed.
+    pub method: Method,
+    /// The `Chunker::max_size()` used, if any.
+    pub max_size: Option<usize>,
+    /// Index of the first chunk that differs from `slices()`.
+    pub chunk: usize,
+    /// Offset of the start of that chunk.
+    pub offset: usize,
+    /// The length of the chunk from `slices()`, or `None` if it had no more.
+    pub expected: Option<usize>,
+    /// The length of the chunk from `method`, or `None` if it had no more.
+    pub actual: Option<usize>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.method)?;
+        if let Some(max_size) = self.max_size {
+            write!(f, " and max_size({})", max_size)?;
+        }
+        write!(f, ": chunk {} at offset {} ", self.chunk, self.offset)?;
+        match (self.expected, self.actual) {
+            (Some(expected), Some(actual)) => {
+                write!(f, "has length {} instead of {}", actual, expected)
+            }
+            (Some(expected), None) => write!(f, "of length {} is missing", expected),
+            (_, Some(actual)) => write!(f, "of length {} is extra", actual),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+/// A reader returning the data in parts of the given sizes.
+struct PartReader<'a> {
+    data: &'a [u8],
+    method: Method,
+    rng: SplitMix64,
+}
+
+impl<'a> Read for PartReader<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let size = match self.method {
+            Method::Stream(size) => size,
+            Method::StreamRandom => 1 + (self.rng.next() % MAX_RANDOM_READ as u64) as usize,
+        };
+        let size = min(min(size, buf.len()), self.data.len());
+        buf[..size].copy_from_slice(&self.data[..size]);
+        self.data = &self.data[size..];
+        Ok(size)
+    }
+}
+
+/// Gets the lengths of the chunks from `slices()`.
+fn slice_lengths<I: ChunkerImpl>(chunker: Chunker<I>, data: &[u8]) -> Vec<usize> {
+    chunker.slices(data).map(|c| c.len()).collect()
+}
+
+/// Gets the lengths of the chunks from `stream()`.
+fn stream_lengths<I: ChunkerImpl>(chunker: Chunker<I>, data: &[u8], method: Method) -> Vec<usize> {
+    let capacity = match method {
+        Method::Stream(size) => size,
+        Method::StreamRandom => MAX_RANDOM_READ,
+    };
+    let reader = PartReader {
+        data,
+        method,
+        rng: SplitMix64(capacity as u64),
+    };
+    let mut stream = chunker.stream_with_capacity(reader, capacity);
+    let mut lengths = Vec::new();
+    let mut length = 0;
+    while let Some(input) = stream.read() {
+        match input.unwrap() {
+            ChunkInput::Data(d) => length += d.len(),
+            // The stream returns an empty chunk for empty data, slices() none
+            ChunkInput::End if length == 0 => {}
+            ChunkInput::End => {
+                lengths.push(length);
+                length = 0;
+            }
+        }
+    }
+    lengths
+}
+
+/// Finds the first difference between two lists of chunk lengths.
+fn compare(
+    expected: &[usize],
+    actual: &[usize],
+    method: Method,
+    max_size: Option<usize>,
+) -> Result<(), Divergence> {
+    let mut offset = 0;
+    for chunk in 0..expected.len().max(actual.len()) {
+        let (e, a) = (expected.get(chunk).cloned(), actual.get(chunk).cloned());
+        if e != a {
+            return Err(Divergence {
+                method,
+                max_size,
+                chunk,
+                offset,
+                expected: e,
+                actual: a,
+            });
+        }
+        offset += e.unwrap();
+    }
+    Ok(())
+}
+
+/// Checks that a chunker finds the same boundaries however the data is read.
+///
+/// The chunks from `Chunker::slices()`, which gives all the data to the
+/// chunker at once, are compared to the chunks from `Chunker::stream()` with
+/// reads of many different sizes, from 1 byte to a few KiB, and of random
+/// sizes. This is done again wrapping the chunker with `Chunker::max_size()`,
+/// which also gives it data cut at arbitrary points. `new_chunker` is called to
+/// get a fresh chunker each time.
+///
+/// Returns the first difference found. Chunkers that can't choose boundaries
+/// one byte at a time should say so with `ChunkerImpl::lookahead()`.
+///
+/// ```
+/// # use cdchunking::FastCDC;
+/// # use cdchunking::testing::{check_conformance, random_data};
+/// let data = random_data(1 << 16, 1);
+/// let result = check_conformance(|| FastCDC::new(256, 1024, 4096), &data);
+/// if let Err(divergence) = result {
+///     panic!("{}", divergence);
+/// }
+/// ```
+pub fn check_conformance<I, F>(mut new_chunker: F, data: &[u8]) -> Result<(), Divergence>
+where
+    I: ChunkerImpl,
+    F: FnMut() -> I,
+{
+    let methods = READ_SIZES
+        .iter()
+        .map(|&size| Method::Stream(size))
+        .chain(Some(Method::StreamRandom));
+
+    let expected = slice_lengths(Chunker::new(new_chunker()), data);
+    for method in methods.clone() {
+        let actual = stream_lengths(Chunker::new(new_chunker()), data, method);
+        compare(&expected, &actual, method, None)?;
+    }
+
+    // Maximum sizes around the average, and small ones
+    let average = data.len() / expected.len().max(1);
+    for &max_size in &[1, 7, average / 2 + 1, average * 2 + 1] {
+        let mut chunker = || Chunker::new(new_chunker()).max_size(max_size);
+        let expected = slice_lengths(chunker(), data);
+        for method in methods.clone() {
+            let actual = stream_lengths(chunker(), data, method);
+            compare(&expected, &actual, method, Some(max_size))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{boundary_shift, check_conformance, random_data, Divergence, Edit, Method};
+    use {
+        AEChunker, AdlerChunker, BFBCChunker, BuzhashChunker, Chunker, ChunkerImpl, FastCDC,
+        FastCDC2020, FixedSizeChunker, GearChunker, MIIChunker, NormalizedChunkingGearChunker,
+        PCIChunker, Polynomial, RAMChunker, RabinChunker, RabinHash, ResticChunker, TTTDChunker,
+        ZPAQCompatible, ZPAQ,
+    };
+
+    type NewChunker = Box<dyn Fn() -> Box<dyn ChunkerImpl>>;
+
+    const EDITS: &[Edit] = &[
+        Edit::Insert {
+            offset: 100_000,
+            length: 3,
+        },
+        Edit::Insert {
+            offset: 0,
+            length: 1,
+        },
+        Edit::Delete {
+            offset: 50_000,
+            length: 100,
+        },
+        Edit::Delete {
+            offset: 0,
+            length: 7,
+        },
+        Edit::Overwrite {
+            offset: 150_000,
+            length: 10,
+        },
+    ];
+
+    #[test]
+    fn test_apply() {
+        let data = random_data(10, 1);
+        assert_eq!(data, random_data(10, 1));
+        assert_eq!(data[..8], random_data(8, 1)[..]);
+
+        let edit = Edit::Insert {
+            offset: 2,
+            length: 3,
+        };
+        let result = edit.apply(&data, 2);
+        assert_eq!(result[..2], data[..2]);
+        assert_eq!(result[5..], data[2..]);
+        let edit = Edit::Delete {
+            offset: 2,
+            length: 3,
+        };
+        assert_eq!(edit.apply(&data, 2)[2..], data[5..]);
+        let edit = Edit::Overwrite {
+            offset: 8,
+            length: 2,
+        };
+        assert_eq!(edit.apply(&data, 2).len(), 10);
+    }
+
+    #[test]
+    fn test_fixed_size() {
+        let data = random_data(1 << 18, 19);
+        for edit in EDITS {
+            let report = boundary_shift(|| FixedSizeChunker::new(1000), &data, edit, 20);
+            assert_eq!(report.preserved_before, report.boundaries_before);
+            match *edit {
+                // Every following boundary is shifted
+                Edit::Insert { .. } | Edit::Delete { .. } => {
+                    assert_eq!(report.preserved_after, 0);
+                    assert_eq!(report.resync_distance, None);
+                }
+                Edit::Overwrite { .. } => {
+                    assert_eq!(report.preserved_after, report.boundaries_after);
+                }
+            }
+        }
+    }
+
+    /// Content-defined chunkers with an average size around 1 KiB.
+    fn content_defined() -> Vec<(&'static str, NewChunker)> {
+        vec![
+            ("gear", Box::new(|| Box::new(GearChunker::new(0xffc0_0000)))),
+            (
+                "fastcdc",
+                Box::new(|| Box::new(FastCDC::new(256, 1024, 4096))),
+            ),
+            (
+                "fastcdc2020",
+                Box::new(|| Box::new(FastCDC2020::new(256, 1024, 4096))),
+            ),
+            ("zpaq", Box::new(|| Box::new(ZPAQ::new(10)))),
+            ("ae", Box::new(|| Box::new(AEChunker::new(600)))),
+            ("ram", Box::new(|| Box::new(RAMChunker::new(600)))),
+            ("mii", Box::new(|| Box::new(MIIChunker::new(6)))),
+            ("pci", Box::new(|| Box::new(PCIChunker::<5>::new(30)))),
+            (
+                "rabin",
+                Box::new(|| Box::new(RabinChunker::new(Polynomial::generate(5), 48, 0x3ff, 0))),
+            ),
+            (
+                "bfbc",
+                Box::new(|| Box::new(BFBCChunker::new((0..64).map(|b| (b, b)).collect(), 256))),
+            ),
+            (
+                "gear with max_size",
+                Box::new(|| {
+                    Box::new(
+                        Chunker::new(GearChunker::new(0xffc0_0000))
+                            .max_size(2048)
+                            .inner,
+                    )
+                }),
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_content_defined() {
+        let data = random_data(1 << 18, 19);
+        for (name, new_chunker) in content_defined() {
+            for edit in EDITS {
+                let report = boundary_shift(&new_chunker, &data, edit, 20);
+                // The data before the edit is chunked the same
+                assert_eq!(report.preserved_before, report.boundaries_before);
+                assert!(report.boundaries_after > 50, "{} makes few chunks", name);
+                // The original boundaries are found again within a few chunks
+                let distance = report.resync_distance.expect(name);
+                assert!(distance <= 8192, "{} resyncs after {}", name, distance);
+                assert!(report.preserved_ratio_after() > 0.99, "{}", name);
+            }
+        }
+    }
+
+    /// Forgets the bytes it was given if it doesn't find a boundary in them.
+    struct Forgetful;
+
+    impl ChunkerImpl for Forgetful {
+        fn find_boundary(&mut self, data: &[u8]) -> Option<usize> {
+            if data.len() >= 100 {
+                Some(99)
+            } else {
+                None
+            }
+        }
+
+        fn reset(&mut self) {}
+    }
+
+    #[test]
+    fn test_conformance() {
+        let data = random_data(1 << 14, 21);
+        let mut chunkers = content_defined();
+        chunkers.push(("fixed", Box::new(|| Box::new(FixedSizeChunker::new(1000)))));
+        chunkers.push((
+            "normalized gear",
+            Box::new(|| {
+                Box::new(NormalizedChunkingGearChunker::new(
+                    0xffe0_0000,
+                    0xff80_0000,
+                    1024,
+                ))
+            }),
+        ));
+        chunkers.push((
+            "adler",
+            Box::new(|| Box::new(AdlerChunker::new(64, 0xffc0_0000))),
+        ));
+        chunkers.push((
+            "buzhash",
+            Box::new(|| Box::new(BuzhashChunker::new(63, 1, 10, 8, 12))),
+        ));
+        chunkers.push((
+            "restic",
+            Box::new(|| {
+                Box::new(ResticChunker::with_boundaries(
+                    Polynomial::generate(6),
+                    256,
+                    4096,
+                ))
+            }),
+        ));
+        chunkers.push((
+            "zpaq compatible",
+            Box::new(|| Box::new(ZPAQCompatible::new(0))),
+        ));
+        chunkers.push((
+            "tttd",
+            Box::new(|| {
+                let hash = RabinHash::new(Polynomial::generate(7), 16);
+                Box::new(TTTDChunker::new(hash, 64, 1024, 1024, 128))
+            }),
+        ));
+        chunkers.push((
+            "tttd with min_size",
+            Box::new(|| {
+                let hash = RabinHash::new(Polynomial::generate(7), 16);
+                let chunker = TTTDChunker::new(hash, 64, 1024, 1024, 128);
+                Box::new(Chunker::new(chunker).min_size(300).inner)
+            }),
+        ));
+        chunkers.push((
+            "gear with min_size",
+            Box::new(|| {
+                Box::new(
+                    Chunker::new(GearChunker::new(0xffc0_0000))
+                        .min_size(300)
+                        .inner,
+                )
+            }),
+        ));
+        for (name, new_chunker) in chunkers {
+            if let Err(divergence) = check_conformance(&new_chunker, &data) {
+                panic!("{}: {}", name, divergence);
+            }
+        }
+
+        let divergence = check_conformance(|| Forgetful, &data).unwrap_err();
+        assert_eq!(
+            divergence,
+            Divergence {
+                method: Method::Stream(1),
+                max_size: None,
+                chunk: 0,
+                offset: 0,
+                expected: Some(100),
+                actual: Some(data.len()),
+            }
+        );
+        assert_eq!(
+            divergence.to_string(),
+            "stream() with reads of 1 byte: chunk 0 at offset 0 has length 16384 instead of 100"
+        );
+    }
+}
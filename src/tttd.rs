@@ -0,0 +1,246 @@
+use std::cmp::min;
+
+use {ChunkerImpl, RollingHash};
+
+/// A chunker implementing the Two Thresholds, Two Divisors algorithm.
+///
+/// A boundary is set after a byte if `hash % main_divisor == main_divisor - 1`, once the chunk is
+/// at least `min_size` bytes long. Bytes where `hash % backup_divisor == backup_divisor - 1` are
+/// remembered as backup breakpoints; if the chunk reaches `max_size` bytes, it is cut at the last
+/// backup breakpoint instead, or at `max_size` if there is none. The backup divisor should be
+/// smaller than the main divisor, so that backup breakpoints are more frequent.
+///
+/// Any `RollingHash` can be used, for example a `RabinHash`. The first `min_size - window_size`
+/// bytes of each chunk are not hashed.
+///
+/// A backup breakpoint is only known to be used once `max_size` is reached, so `lookahead()` is
+/// `max_size`: the streams keep up to `max_size` bytes in memory and give them to `find_boundary()`
+/// at once, and chunks are the same as with `Chunker::slices()` whatever the read sizes. If the data
+/// is given in smaller parts anyway, a backup breakpoint in a previous part can't be used, and the
+/// chunk is cut at `max_size` instead.
+///
+/// Source: Kave Eshghi and Hsiu Khuern Tang. "A framework for analyzing and improving
+/// content-based chunking algorithms." Hewlett-Packard Labs Technical Report TR 30 (2005).
+/// PDF: https://www.hpl.hp.com/techreports/2005/HPL-2005-30R1.pdf
+#[derive(Debug, Clone)]
+pub struct TTTDChunker<H: RollingHash> {
+    hash: H,
+    min_size: usize,
+    max_size: usize,
+    main_divisor: u64,
+    backup_divisor: u64,
+    state: TTTDState,
+}
+
+impl<H: RollingHash> TTTDChunker<H> {
+    /// Creates a new TTTD chunker.
+    ///
+    /// The paper suggests `min_size = 460`, `max_size = 2800`, `main_divisor = 540` and
+    /// `backup_divisor = 270` for an average chunk size of about 1 KiB.
+    pub fn new(
+        hash: H,
+        min_size: usize,
+        max_size: usize,
+        main_divisor: u64,
+        backup_divisor: u64,
+    ) -> TTTDChunker<H> {
+        assert!(min_size > 0, "min_size needs to be at least 1");
+        assert!(
+            min_size <= max_size,
+            "min_size needs to be at most max_size"
+        );
+        assert!(main_divisor > 0 && backup_divisor > 0);
+        TTTDChunker {
+            hash,
+            min_size,
+            max_size,
+            main_divisor,
+            backup_divisor,
+            state: Default::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct TTTDState {
+    /// The number of bytes in the current chunk so far.
+    pos: usize,
+
+    /// The size the chunk would have if cut at the last backup breakpoint.
+    backup: Option<usize>,
+}
+
+impl<H: RollingHash> ChunkerImpl for TTTDChunker<H> {
+    fn find_boundary(&mut self, data: &[u8]) -> Option<usize> {
+        let start_pos = self.state.pos;
+
+        // Skip the bytes that can't influence the first hash we check
+        let skip = self
+            .min_size
+            .saturating_sub(self.hash.window_size())
+            .saturating_sub(start_pos);
+        let start = min(skip, data.len());
+        self.state.pos += start;
+
+        for (i, &b) in data.iter().enumerate().skip(start) {
+            self.hash.slide(b);
+            self.state.pos += 1;
+            if self.state.pos < self.min_size {
+                continue;
+            }
+
+            let digest = self.hash.digest();
+            if digest % self.backup_divisor == self.backup_divisor - 1 {
+                self.state.backup = Some(self.state.pos);
+            }
+            if digest % self.main_divisor == self.main_divisor - 1 {
+                return Some(i);
+            }
+            if self.state.pos >= self.max_size {
+                return match self.state.backup {
+                    // The backup breakpoint is in this block of data
+                    Some(backup) if backup > start_pos => Some(backup - start_pos - 1),
+                    _ => Some(i),
+                };
+            }
+        }
+
+        // No cut-point found within this block of data.
+        None
+    }
+
+    fn reset(&mut self) {
+        self.hash.reset();
+        self.state = Default::default();
+    }
+
+    fn lookahead(&self) -> Option<usize> {
+        Some(self.max_size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::io::{BufReader, Write};
+
+    use super::TTTDChunker;
+    use {ChunkInput, Chunker, Polynomial, RabinHash, RollingHash};
+
+    /// Straightforward implementation of TTTD from the paper, hashing every byte.
+    fn reference(data: &[u8], hash: &mut RabinHash) -> Vec<usize> {
+        let mut sizes = Vec::new();
+        let (mut last, mut backup) = (0, 0);
+        let mut p = 0;
+        while p < data.len() {
+            hash.slide(data[p]);
+            p += 1;
+            if p - last < 460 {
+                continue;
+            }
+            let digest = hash.digest();
+            if digest % 270 == 269 {
+                backup = p;
+            }
+            if digest % 540 == 539 {
+                sizes.push(p - last);
+                last = p;
+                backup = 0;
+                hash.reset();
+                continue;
+            }
+            if p - last < 2800 {
+                continue;
+            }
+            if backup != 0 {
+                sizes.push(backup - last);
+                last = backup;
+                backup = 0;
+            } else {
+                sizes.push(p - last);
+                last = p;
+            }
+            p = last;
+            hash.reset();
+        }
+        if last < data.len() {
+            sizes.push(data.len() - last);
+        }
+        sizes
+    }
+
+    #[test]
+    fn test_reference() {
+        let mut data = vec![0u8; 1 << 20];
+        StdRng::seed_from_u64(9).fill(&mut data[..]);
+        let hash = RabinHash::new(Polynomial::generate(2), 48);
+
+        let result: Vec<_> = Chunker::new(TTTDChunker::new(hash.clone(), 460, 2800, 540, 270))
+            .slices(&data)
+            .map(|c| c.len())
+            .collect();
+        assert_eq!(result, reference(&data, &mut hash.clone()));
+
+        let (_, rest) = result.split_last().unwrap();
+        assert!(rest.iter().all(|&l| (460..=2800).contains(&l)));
+        // Some chunks were cut at a backup breakpoint
+        assert!(rest.iter().any(|&l| l > 1500 && l < 2800));
+    }
+
+    #[test]
+    fn test_stream() {
+        // Chunks cut at backup breakpoints are the same when streaming
+        let mut data = vec![0u8; 1 << 18];
+        StdRng::seed_from_u64(10).fill(&mut data[..]);
+        let hash = RabinHash::new(Polynomial::generate(2), 48);
+        let chunker = || Chunker::new(TTTDChunker::new(hash.clone(), 460, 2800, 540, 270));
+        let expected = reference(&data, &mut hash.clone());
+        assert!(expected.iter().any(|&l| l > 1500 && l < 2800));
+
+        let result: Vec<_> = chunker()
+            .chunks(&data[..])
+            .map(|c| c.unwrap().length())
+            .collect();
+        assert_eq!(result, expected);
+
+        let mut stream = chunker().stream_with_capacity(&data[..], 1000);
+        let mut result = vec![0];
+        while let Some(input) = stream.read() {
+            match input.unwrap() {
+                ChunkInput::Data(d) => *result.last_mut().unwrap() += d.len(),
+                ChunkInput::End => result.push(0),
+            }
+        }
+        assert_eq!(result.pop(), Some(0));
+        assert_eq!(result, expected);
+
+        let mut stream = chunker().stream_bufread(BufReader::with_capacity(100, &data[..]));
+        let mut result = vec![0];
+        while let Some(input) = stream.read() {
+            match input.unwrap() {
+                ChunkInput::Data(d) => *result.last_mut().unwrap() += d.len(),
+                ChunkInput::End => result.push(0),
+            }
+        }
+        assert_eq!(result.pop(), Some(0));
+        assert_eq!(result, expected);
+
+        let mut result = vec![0];
+        {
+            let mut writer = chunker().writer(|input| {
+                match input {
+                    ChunkInput::Data(d) => *result.last_mut().unwrap() += d.len(),
+                    ChunkInput::End => result.push(0),
+                }
+                Ok(())
+            });
+            for part in data.chunks(700) {
+                writer.write_all(part).unwrap();
+            }
+            let _sink = writer.finish().unwrap();
+        }
+        assert_eq!(result.pop(), Some(0));
+        assert_eq!(result, expected);
+    }
+}
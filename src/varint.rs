@@ -0,0 +1,54 @@
+//! LEB128 variable-length integers, used by the binary encodings.
+
+use std::io::{self, Read, Write};
+
+pub(crate) fn write_varint<W: Write>(writer: &mut W, mut value: u64) -> io::Result<()> {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            return writer.write_all(&[byte]);
+        }
+        writer.write_all(&[byte | 0x80])?;
+    }
+}
+
+pub(crate) fn read_varint<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut value = 0u64;
+    let mut shift = 0;
+    loop {
+        let mut byte = [0u8];
+        reader.read_exact(&mut byte)?;
+        if shift > 63 || (shift == 63 && byte[0] > 1) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "integer too large",
+            ));
+        }
+        value |= ((byte[0] & 0x7f) as u64) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(value);
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::{read_varint, write_varint};
+
+    #[test]
+    fn test_varint() {
+        for &value in &[0, 1, 127, 128, 300, 1 << 40, u64::MAX] {
+            let mut encoded = Vec::new();
+            write_varint(&mut encoded, value).unwrap();
+            assert_eq!(read_varint(&mut Cursor::new(&encoded)).unwrap(), value);
+        }
+        let mut encoded = Vec::new();
+        write_varint(&mut encoded, 300).unwrap();
+        assert_eq!(encoded, [0xac, 0x02]);
+        assert!(read_varint(&mut &[0xff; 10][..]).is_err());
+    }
+}
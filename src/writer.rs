@@ -0,0 +1,141 @@
+use std::io::{self, Write};
+
+use {ChunkInput, ChunkerImpl, Lookahead};
+
+/// Splits the data written to it into chunks, passing them to a sink.
+///
+/// This is created by `Chunker::writer()`. The sink is called with the same
+/// `ChunkInput` items that `ChunkStream::read()` returns: `Data` for the parts
+/// of the current chunk, in order, and `End` at each boundary. The data given
+/// to the sink is borrowed from the buffers passed to `write()`, so there is no
+/// copy.
+///
+/// `flush()` doesn't end the current chunk, since that would make boundaries
+/// depend on when it is called; use `finish()` once all the data is written.
+/// If the sink returns an error, it is returned by `write()`, and the writer
+/// should not be used anymore.
+///
+/// If the chunking method has a `ChunkerImpl::lookahead()`, the data is copied
+/// to a buffer until enough of it was written, and only given to the sink then.
+pub struct ChunkWriter<I: ChunkerImpl, F: FnMut(ChunkInput) -> io::Result<()>> {
+    inner: I,
+    sink: F,
+    in_chunk: bool, // Whether we emitted Data since the last End
+    lookahead: Option<Lookahead>,
+}
+
+impl<I: ChunkerImpl, F: FnMut(ChunkInput) -> io::Result<()>> ChunkWriter<I, F> {
+    pub(crate) fn new(inner: I, sink: F) -> ChunkWriter<I, F> {
+        let lookahead = inner.lookahead().map(Lookahead::new);
+        ChunkWriter {
+            inner,
+            sink,
+            in_chunk: false,
+            lookahead,
+        }
+    }
+
+    /// Adds data to the lookahead buffer, empty meaning the end, and gives the
+    /// sink the chunks that can be found.
+    fn push_lookahead(&mut self, data: &[u8]) -> io::Result<()> {
+        let lookahead = self.lookahead.as_mut().unwrap();
+        lookahead.push(data);
+        while let Some(input) = lookahead.next(&mut self.inner) {
+            self.in_chunk = match input {
+                ChunkInput::Data(_) => true,
+                ChunkInput::End => false,
+            };
+            (self.sink)(input)?;
+        }
+        Ok(())
+    }
+
+    /// Ends the last chunk, and returns the sink.
+    pub fn finish(mut self) -> io::Result<F> {
+        if self.lookahead.is_some() {
+            self.push_lookahead(&[])?;
+        }
+        if self.in_chunk {
+            (self.sink)(ChunkInput::End)?;
+        }
+        Ok(self.sink)
+    }
+}
+
+impl<I: ChunkerImpl, F: FnMut(ChunkInput) -> io::Result<()>> Write for ChunkWriter<I, F> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.lookahead.is_some() {
+            if !buf.is_empty() {
+                self.push_lookahead(buf)?;
+            }
+            return Ok(buf.len());
+        }
+
+        let mut pos = 0;
+        while pos < buf.len() {
+            match self.inner.find_boundary(&buf[pos..]) {
+                Some(split) => {
+                    assert!(pos + split < buf.len());
+                    (self.sink)(ChunkInput::Data(&buf[pos..pos + split + 1]))?;
+                    (self.sink)(ChunkInput::End)?;
+                    self.inner.reset();
+                    self.in_chunk = false;
+                    pos += split + 1;
+                }
+                None => {
+                    (self.sink)(ChunkInput::Data(&buf[pos..]))?;
+                    self.in_chunk = true;
+                    pos = buf.len();
+                }
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{self, Rng};
+    use std::io::{self, Write};
+
+    use {ChunkInput, Chunker, GearChunker};
+
+    #[test]
+    fn test_writer() {
+        let mut data = vec![0u8; 1 << 14];
+        rand::thread_rng().fill(&mut data[..]);
+        let chunker = || Chunker::new(GearChunker::new(0xfc00_0000));
+        let expected: Vec<_> = chunker().slices(&data).collect();
+
+        let mut chunks = vec![Vec::new()];
+        {
+            let mut writer = chunker().writer(|input| {
+                match input {
+                    ChunkInput::Data(d) => chunks.last_mut().unwrap().extend_from_slice(d),
+                    ChunkInput::End => chunks.push(Vec::new()),
+                }
+                Ok(())
+            });
+            // Write in pieces of varying sizes
+            let mut pos = 0;
+            let mut size = 1;
+            while pos < data.len() {
+                let end = ::std::cmp::min(pos + size, data.len());
+                writer.write_all(&data[pos..end]).unwrap();
+                pos = end;
+                size = size * 3 % 1000 + 1;
+            }
+            let _sink = writer.finish().unwrap();
+        }
+        assert_eq!(chunks.pop(), Some(Vec::new()));
+        assert_eq!(chunks, expected);
+
+        // Errors from the sink are returned
+        let mut writer = chunker().writer(|_| Err(io::Error::new(io::ErrorKind::Other, "sink")));
+        assert!(writer.write(&data).is_err());
+    }
+}
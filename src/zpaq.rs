@@ -0,0 +1,170 @@
+use std::cmp::min;
+
+use ChunkerImpl;
+
+/// ZPAQ chunking algorithm, as implemented by the reference `zpaq` archiver.
+///
+/// This uses the hash constants of the reference implementation, and limits
+/// fragments to between `64 << fragment` and `8128 << fragment` bytes (4096
+/// and 520192 bytes for the default `fragment = 6`). The average size is about
+/// `1 << (fragment + 10)` bytes.
+///
+/// Like in `zpaq`, the hash, previous byte and order-1 prediction table are
+/// reset after each fragment.
+///
+/// This follows the fragmentation code of `zpaq` 7.15, and gives the same
+/// fragments as `zpaq add -fragment N` (see `tests/fixtures/README.md`).
+pub struct ZPAQCompatible {
+    fragment: u32,
+    min_size: usize,
+    max_size: usize,
+    c1: u8, // previous byte
+    o1: [u8; 256],
+    h: u32,
+    sz: usize,
+}
+
+impl ZPAQCompatible {
+    /// Creates a chunker matching `zpaq -fragment N`, the default being 6.
+    pub fn new(fragment: u32) -> ZPAQCompatible {
+        assert!(fragment <= 22, "fragment needs to be at most 22");
+        ZPAQCompatible {
+            fragment,
+            min_size: 64 << fragment,
+            // Doesn't fit in 32 bits for large fragments, which is no limit there
+            max_size: min(8128u64 << fragment, usize::MAX as u64) as usize,
+            c1: 0,
+            o1: [0; 256],
+            h: 0,
+            sz: 0,
+        }
+    }
+}
+
+impl ChunkerImpl for ZPAQCompatible {
+    fn find_boundary(&mut self, data: &[u8]) -> Option<usize> {
+        for (i, &c) in data.iter().enumerate() {
+            if c == self.o1[self.c1 as usize] {
+                self.h = self.h.wrapping_add(c as u32 + 1).wrapping_mul(314_159_265);
+            } else {
+                self.h = self.h.wrapping_add(c as u32 + 1).wrapping_mul(271_828_182);
+            }
+            self.o1[self.c1 as usize] = c;
+            self.c1 = c;
+            self.sz += 1;
+
+            if self.sz >= self.max_size
+                || (self.h < (1 << (22 - self.fragment)) && self.sz >= self.min_size)
+            {
+                return Some(i);
+            }
+        }
+        None
+    }
+
+    fn reset(&mut self) {
+        self.c1 = 0;
+        self.o1 = [0; 256];
+        self.h = 0;
+        self.sz = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::cmp::min;
+
+    use super::ZPAQCompatible;
+    use rabin::tests::generate;
+    use Chunker;
+
+    /// Generates text-like data, lines of two numbers.
+    fn text(len: usize) -> Vec<u8> {
+        let mut data = Vec::with_capacity(len);
+        let mut i = 0u64;
+        while data.len() < len {
+            data.extend(format!("{} {}\n", i, i * 7919 % 10007).bytes());
+            i += 1;
+        }
+        data.truncate(len);
+        data
+    }
+
+    fn check_fixture(data: &[u8], fragment: u32, fixture: &str) {
+        let expected: Vec<usize> = fixture.lines().map(|l| l.parse().unwrap()).collect();
+
+        let result: Vec<_> = Chunker::new(ZPAQCompatible::new(fragment))
+            .slices(data)
+            .map(|c| c.len())
+            .collect();
+        assert_eq!(result, expected);
+
+        let result: Vec<_> = Chunker::new(ZPAQCompatible::new(fragment))
+            .chunks(data)
+            .map(|c| c.unwrap().length())
+            .collect();
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_zpaq_compatible() {
+        // fragment = 0: between 64 and 8128 bytes, about 1 KiB on average
+        let mut data = vec![0u8; 1 << 20];
+        StdRng::seed_from_u64(19).fill(&mut data[..]);
+
+        let sizes: Vec<_> = Chunker::new(ZPAQCompatible::new(0))
+            .slices(&data)
+            .map(|c| c.len())
+            .collect();
+        let (_, rest) = sizes.split_last().unwrap();
+        assert!(rest.iter().all(|&l| (64..=8128).contains(&l)));
+        let average = data.len() / sizes.len();
+        assert!(900 < average && average < 1300);
+
+        let result: Vec<_> = Chunker::new(ZPAQCompatible::new(0))
+            .chunks(&data[..])
+            .map(|c| c.unwrap().length())
+            .collect();
+        assert_eq!(result, sizes);
+
+        // The hash is reset after each fragment, so a run of the same byte is
+        // cut at regular intervals
+        let zeros = vec![0u8; 20000];
+        let sizes: Vec<_> = Chunker::new(ZPAQCompatible::new(0))
+            .slices(&zeros)
+            .map(|c| c.len())
+            .collect();
+        let (last, rest) = sizes.split_last().unwrap();
+        assert!(rest.iter().all(|&l| l == rest[0]));
+        assert!(*last <= rest[0]);
+
+        // Limits for the default fragment = 6, and the largest one
+        let chunker = ZPAQCompatible::new(6);
+        assert_eq!((chunker.min_size, chunker.max_size), (4096, 520192));
+        let chunker = ZPAQCompatible::new(22);
+        assert_eq!(chunker.max_size as u64, min(8128 << 22, usize::MAX as u64));
+    }
+
+    #[test]
+    fn test_zpaq_fixtures() {
+        // Fragments recorded by zpaq 7.15, see tests/fixtures/README.md
+        let random = generate(4 * 1024 * 1024, 0x7a70);
+        check_fixture(
+            &random,
+            0,
+            include_str!("../tests/fixtures/zpaq-random-0.txt"),
+        );
+        check_fixture(
+            &random,
+            6,
+            include_str!("../tests/fixtures/zpaq-random-6.txt"),
+        );
+        check_fixture(
+            &text(1024 * 1024),
+            0,
+            include_str!("../tests/fixtures/zpaq-text-0.txt"),
+        );
+    }
+}
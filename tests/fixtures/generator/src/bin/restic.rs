@@ -0,0 +1,88 @@
+//! Prints the chunk sizes of `restic.txt`.
+//!
+//! This chunks the data with a port of rustic's restic-compatible chunker
+//! (`ChunkIter` in rustic_core's `src/chunker/rabin.rs`, over the `Rabin64`
+//! hash of rustic_cdc), after checking it against rustic_core's own test
+//! vectors.
+
+use rand::prelude::*;
+use rustic_cdc::{Rabin64, RollingHash64};
+use sha2::{Digest, Sha256};
+
+const POLYNOMIAL: u64 = 0x003D_A335_8B4D_C173;
+const MIN_SIZE: usize = 512 * 1024;
+const MAX_SIZE: usize = 8 * 1024 * 1024;
+const SPLIT_MASK: u64 = (1 << 20) - 1;
+
+/// rustic_core's `chunk_random` snapshot: 32 MiB from `StdRng` seeded with 23.
+const CHUNK_RANDOM: &[(usize, &str)] = &[
+    (849138, "cc6e28740254311145d07d374153b80967594471c5f84a32e55101d404877733"),
+    (3400295, "fe11521fda1dd7cedccd70aa63dedb4f3e403df689675a01090c6f89729e8d81"),
+    (696162, "2acf89a22eec4c78956a986e43d5c0a5a9a251faea0e970920ed4fb8d8eb55b4"),
+    (572781, "a2031c528b828c87f7c4555127b01e72d7272de9758ff87f5c2ef36fcea36d31"),
+    (1130212, "1f1af73e3025d8310bb7f5e743032fd7b80352d918c1a407a3971d1714a7e6bc"),
+    (878812, "3a763ed3ea881d9a55f1b34c87f49e74a89e03ffe32d4269629d3291a0656184"),
+    (1329543, "538294400c2c42e478f5d9b12b175d925bac93a8af707cba2841d75e6bca9449"),
+    (776749, "c8924f66430597edb2ce63226936e8bf4a4899c9a34fe531add93fecacaea69f"),
+    (1442260, "074bc7a49517016ab82e72d9a97f03ab068d747e0c789d48c1a7b16137637ee3"),
+    (1114190, "670d672f02634dd1e081fad12d5f626811767d79615602bf4ea3e1685620f054"),
+    (866219, "2771cbfbd7b9aafc42268b42f676cb73d72b1389417a8fd2e6d07fb12e0c8dae"),
+    (1366251, "ce410725064d13a46ad9b7bb7e334beb58cb6058769a844c1b55e73a95e81ed7"),
+    (779036, "df6d433ea40d69157572dca65c40da3c65ac0b0de2f6e65bc144ad695495884d"),
+    (1132888, "976970254546211a0294877d488ed695eae55f6dadb83058eb739a9b6f2402cb"),
+    (1318220, "60fb13ace728a55d1e84f9bfd1e5994207a5c8c63357ddbcdd2548794a10260c"),
+    (614940, "8fdacd109efc1dbf18a498fe28713fd338ae7bd551152de4915ae3a31966fd52"),
+    (1259423, "e8078cc71abb963e1150bc2ed1a51976eb91758760f7ff07c334fb401341fb9f"),
+    (1446827, "edba90c716617b07e6519bd8a10307f5d4c38927ece4a41d6bf1161cbfc8efa6"),
+    (2887476, "072251a4d416aefee7b2d5956f68d3cb9cd40af197f200678037795303b7d1bf"),
+    (1740569, "ae924786d0ea8ab443c3e66cf8b89b7a59ba2d62136cc84759b74a08b094ad1c"),
+    (1313703, "00266471c92f483b041118ae212f712feb1027c9aed6d25b17315cd1222eb4cf"),
+    (924445, "6374010346fc2de53860c14f1be215942ee4966215cc366c83c5f8f7d4805cb6"),
+    (654785, "06491ea1581ff44fb1f1ddf25e81977ffd49261f265eaead2c116f6b6de86a75"),
+    (877426, "4f144b1fabd4939766d03979a91feaf7f5980bf9df303f6a000d50c9909282e0"),
+    (1012165, "f08d3907ab9c71f020cc190b79e776db07d1dcb1599be2b0db2bd7458618e69b"),
+    (1129074, "a038fee065c41d94e6078b25aef034530e034ad0cfce2cc83ea44f10176b1d58"),
+    (937699, "4d0a61afc1b7fa35ea6e497e5b57facadff18c3afc88c564be0721a393fffb95"),
+    (748040, "de1b721c2c11a971f1c01e5bda47af99a2c2f4790165fbfd362242c43b36602f"),
+    (355104, "558260efe0eae3e049bf439045f73fd91e15808567c051aea2571ade2a902473"),
+];
+
+fn chunk(data: &[u8]) -> Vec<&[u8]> {
+    let mut rabin = Rabin64::new_with_polynom(6, &POLYNOMIAL);
+    let mut chunks = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        if data.len() - pos <= MIN_SIZE {
+            chunks.push(&data[pos..]);
+            break;
+        }
+        let mut len = MIN_SIZE;
+        rabin.reset_and_prefill_window(&mut data[pos + len - 64..pos + len].iter().copied());
+        while len < MAX_SIZE && rabin.hash & SPLIT_MASK != 0 && pos + len < data.len() {
+            rabin.slide(data[pos + len]);
+            len += 1;
+        }
+        chunks.push(&data[pos..pos + len]);
+        pos += len;
+    }
+    chunks
+}
+
+fn main() {
+    let mut data = vec![0u8; 32 * 1024 * 1024];
+    StdRng::seed_from_u64(23).fill_bytes(&mut data);
+    let chunks: Vec<_> = chunk(&data)
+        .into_iter()
+        .map(|c| (c.len(), format!("{:x}", Sha256::digest(c))))
+        .collect();
+    let expected: Vec<_> = CHUNK_RANDOM
+        .iter()
+        .map(|&(l, h)| (l, h.to_owned()))
+        .collect();
+    assert_eq!(chunks, expected, "doesn't match rustic_core's test vectors");
+
+    let data = cdchunking_fixtures::random(16 * 1024 * 1024, 0x7265_7374_6963);
+    for c in chunk(&data) {
+        println!("{}", c.len());
+    }
+}
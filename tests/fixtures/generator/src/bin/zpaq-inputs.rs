@@ -0,0 +1,16 @@
+//! Writes the input files of the `zpaq-*.txt` fixtures to the given directory.
+
+use std::env;
+use std::fs;
+use std::path::Path;
+
+fn main() {
+    let dir = env::args().nth(1).expect("Usage: zpaq-inputs DIRECTORY");
+    let dir = Path::new(&dir);
+    fs::write(
+        dir.join("random"),
+        cdchunking_fixtures::random(4 * 1024 * 1024, 0x7a70),
+    )
+    .unwrap();
+    fs::write(dir.join("text"), cdchunking_fixtures::text(1024 * 1024)).unwrap();
+}
@@ -0,0 +1,29 @@
+//! Input data of the fixtures, generated the same way as in the crate's tests.
+
+/// Pseudo-random data, same as `generate()` in the tests of `src/rabin.rs`.
+pub fn random(len: usize, seed: u64) -> Vec<u8> {
+    let mut state = seed;
+    let mut data = Vec::with_capacity(len);
+    while data.len() < len {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        for i in 0..8 {
+            data.push((state >> (i * 8)) as u8);
+        }
+    }
+    data.truncate(len);
+    data
+}
+
+/// Text-like data, same as `text()` in the tests of `src/zpaq.rs`.
+pub fn text(len: usize) -> Vec<u8> {
+    let mut data = Vec::with_capacity(len);
+    let mut i = 0u64;
+    while data.len() < len {
+        data.extend(format!("{} {}\n", i, i * 7919 % 10007).bytes());
+        i += 1;
+    }
+    data.truncate(len);
+    data
+}
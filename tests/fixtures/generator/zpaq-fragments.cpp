@@ -0,0 +1,91 @@
+// Prints the sizes of the fragments of each file in a zpaq archive, in order,
+// one per line. This reads the fragment tables (h blocks) and the file index
+// (i blocks) written by zpaq, it doesn't split anything itself.
+//
+// Build with: c++ -O2 -Dunix -I$ZPAQ zpaq-fragments.cpp $ZPAQ/libzpaq.cpp -pthread
+
+#include <cstdio>
+#include <cstdlib>
+#include <cstring>
+#include <map>
+#include <string>
+#include <vector>
+#include "libzpaq.h"
+
+void libzpaq::error(const char* msg) {
+  fprintf(stderr, "zpaq-fragments: %s\n", msg);
+  exit(1);
+}
+
+struct File: libzpaq::Reader {
+  FILE* f;
+  int get() { return getc(f); }
+};
+
+struct Buffer: libzpaq::Writer {
+  std::string s;
+  void put(int c) { s += char(c); }
+};
+
+static unsigned btoi(const char*& s) {
+  s += 4;
+  return (s[-4]&255)|((s[-3]&255)<<8)|((s[-2]&255)<<16)|((s[-1]&255)<<24);
+}
+
+int main(int argc, char** argv) {
+  if (argc != 2) {
+    fprintf(stderr, "Usage: zpaq-fragments ARCHIVE\n");
+    return 2;
+  }
+  File in;
+  in.f = fopen(argv[1], "rb");
+  if (!in.f) libzpaq::error("can't open archive");
+  std::map<unsigned, unsigned> sizes;  // fragment ID -> size
+  std::map<std::string, std::vector<unsigned> > files;  // name -> fragment IDs
+  libzpaq::Decompresser d;
+  d.setInput(&in);
+  while (d.findBlock()) {
+    Buffer name;
+    while (d.findFilename(&name)) {
+      Buffer comment;
+      d.readComment(&comment);
+      Buffer out;
+      d.setOutput(&out);
+      d.decompress();
+      d.readSegmentEnd();
+      // Journaling blocks are named jDC<date:14><type><number:10>
+      if (name.s.size() == 28 && name.s.compare(0, 3, "jDC") == 0) {
+        const unsigned num = atoi(name.s.c_str() + 18);
+        const char* s = out.s.c_str();
+        const char* const end = s + out.s.size();
+        if (name.s[17] == 'h') {  // bsize[4] (sha1[20] usize[4])...
+          btoi(s);
+          for (unsigned i = num; s + 24 <= end; ++i) {
+            s += 20;
+            sizes[i] = btoi(s);
+          }
+        }
+        else if (name.s[17] == 'i') {  // (date[8] name 0 na[4] attr ni[4] ptr[4]...)...
+          while (s + 9 <= end) {
+            const bool deleted = !memcmp(s, "\0\0\0\0\0\0\0\0", 8);
+            s += 8;
+            std::string fn = s;
+            s += fn.size() + 1;
+            std::vector<unsigned>& ptr = files[fn];
+            ptr.clear();
+            if (deleted) continue;
+            s += btoi(s);
+            for (unsigned n = btoi(s); n > 0; --n) ptr.push_back(btoi(s));
+          }
+        }
+      }
+      name.s = "";
+    }
+  }
+  for (std::map<std::string, std::vector<unsigned> >::iterator p = files.begin();
+       p != files.end(); ++p) {
+    for (unsigned i = 0; i < p->second.size(); ++i)
+      printf("%u\n", sizes[p->second[i]]);
+  }
+  return 0;
+}
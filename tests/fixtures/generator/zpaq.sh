@@ -0,0 +1,29 @@
+#!/bin/sh
+# Regenerates the zpaq-*.txt fixtures with the reference zpaq archiver.
+#
+# Usage: zpaq.sh ZPAQ_SOURCE_DIRECTORY
+#
+# The directory needs zpaq.cpp, libzpaq.cpp and libzpaq.h from zpaq 7.15
+# (zpaq715.zip). The files are added to an archive by zpaq, and the fragments
+# it recorded are listed by zpaq-fragments.
+
+set -eu
+src=$1
+here=$(cd "$(dirname "$0")" && pwd)
+work=$(mktemp -d)
+trap 'rm -rf "$work"' EXIT
+
+c++ -O2 -Dunix -o "$work/zpaq" "$src/zpaq.cpp" "$src/libzpaq.cpp" -pthread
+c++ -O2 -Dunix -I"$src" -o "$work/zpaq-fragments" "$here/zpaq-fragments.cpp" \
+    "$src/libzpaq.cpp" -pthread
+(cd "$here" && cargo run --release --bin zpaq-inputs -- "$work")
+
+# fixture INPUT FRAGMENT
+fixture() {
+    rm -f "$work/archive.zpaq"
+    (cd "$work" && ./zpaq add archive.zpaq "$1" -fragment "$2" -method 0 >/dev/null 2>&1)
+    "$work/zpaq-fragments" "$work/archive.zpaq" >"$here/../zpaq-$1-$2.txt"
+}
+fixture random 0
+fixture random 6
+fixture text 0